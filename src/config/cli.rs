@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use clap::Parser;
+
+use super::loader::{parse_user, ConfigError, PartialConfig};
+
+/// Prometheus exporter for tado° thermostats.
+///
+/// Every flag can also be set with the matching EXPORTER_* environment variable or
+/// in the config file. Flags take precedence over environment variables, which take
+/// precedence over the config file.
+#[derive(Parser, Debug, Default)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path of a YAML config file [env: EXPORTER_CONFIG_FILE]
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Number of seconds between two scrapes of the tado° API [default: 60]
+    #[arg(long, value_name = "SECONDS")]
+    ticker: Option<u64>,
+
+    /// Seconds to wait for in-flight work to complete on shutdown [default: 10]
+    #[arg(long, value_name = "SECONDS")]
+    shutdown_timeout: Option<u64>,
+
+    /// tado° OAuth client id
+    #[arg(long)]
+    client_id: Option<String>,
+
+    /// File storing the tado° refresh token [default: .tado_token]
+    #[arg(long, value_name = "FILE")]
+    token_file: Option<String>,
+
+    /// Token storage: file, memory or encrypted_file [default: file]
+    #[arg(long, value_name = "STORE")]
+    token_store: Option<String>,
+
+    /// File holding the key of the encrypted_file token store
+    #[arg(long, value_name = "FILE")]
+    token_key_file: Option<String>,
+
+    /// Home identifier or name to scrape, every home when not set
+    #[arg(long, value_name = "HOME", value_delimiter = ',')]
+    homes_include: Option<Vec<String>>,
+
+    /// Home identifier or name to skip
+    #[arg(long, value_name = "HOME", value_delimiter = ',')]
+    homes_exclude: Option<Vec<String>>,
+
+    /// Address to listen on, e.g. [::]:9898 or unix:/run/tado-exporter.sock [default: 0.0.0.0:9898]
+    #[arg(long = "listen-address", value_name = "ADDRESS", value_delimiter = ',')]
+    listen_addresses: Option<Vec<String>>,
+
+    /// Path of an exporter-toolkit web config file
+    #[arg(long = "web.config.file", value_name = "FILE")]
+    web_config_file: Option<String>,
+
+    /// PEM certificate (chain) file to serve HTTPS
+    #[arg(long, value_name = "FILE", requires = "tls_key_file")]
+    tls_cert_file: Option<String>,
+
+    /// PEM private key file to serve HTTPS
+    #[arg(long, value_name = "FILE", requires = "tls_cert_file")]
+    tls_key_file: Option<String>,
+
+    /// Basic auth user allowed to access the endpoints, the password can be a bcrypt hash.
+    /// Other local users can read it from the process list, prefer a web config file
+    #[arg(long = "basic-auth-user", value_name = "USER:PASSWORD")]
+    basic_auth_users: Option<Vec<String>>,
+
+    /// Bearer token allowed to access the endpoints, can be a bcrypt hash.
+    /// Other local users can read it from the process list, prefer --bearer-token-file
+    #[arg(long, value_name = "TOKEN", conflicts_with = "bearer_token_file")]
+    bearer_token: Option<String>,
+
+    /// File holding the bearer token allowed to access the endpoints
+    #[arg(long, value_name = "FILE")]
+    bearer_token_file: Option<String>,
+}
+
+impl Cli {
+    /// Settings given on the command line.
+    pub fn into_partial(self) -> Result<PartialConfig, ConfigError> {
+        Ok(PartialConfig {
+            ticker: self.ticker,
+            shutdown_timeout: self.shutdown_timeout,
+            client_id: self.client_id,
+            token_file: self.token_file,
+            token_store: self.token_store,
+            // The key is only read from a file or the environment, out of the process list.
+            token_key: None,
+            token_key_file: self.token_key_file,
+            homes_include: self.homes_include,
+            homes_exclude: self.homes_exclude,
+            listen_addresses: self.listen_addresses,
+            web_config_file: self.web_config_file,
+            tls_cert_file: self.tls_cert_file,
+            tls_key_file: self.tls_key_file,
+            basic_auth_users: match self.basic_auth_users {
+                Some(users) => Some(
+                    users
+                        .iter()
+                        .map(|u| parse_user("--basic-auth-user", u))
+                        .collect::<Result<HashMap<_, _>, _>>()?,
+                ),
+                None => None,
+            },
+            bearer_token: self.bearer_token,
+            bearer_token_file: self.bearer_token_file,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_into_partial() {
+        /*
+        GIVEN command line flags
+        WHEN they are converted to settings
+        THEN every flag is mapped to its config key
+        */
+
+        // GIVEN
+        let cli = Cli::parse_from([
+            "tado-exporter",
+            "--config",
+            "config.yml",
+            "--ticker",
+            "30",
+            "--homes-include",
+            "123,My Home",
+            "--listen-address",
+            "[::]:9898",
+            "--listen-address",
+            "unix:/run/tado.sock",
+            "--web.config.file",
+            "web.yml",
+            "--basic-auth-user",
+            "prometheus:secret",
+        ]);
+
+        // WHEN
+        let config = cli.config.clone();
+        let actual = cli.into_partial().unwrap();
+
+        // THEN
+        assert_eq!(config, Some("config.yml".to_string()));
+        assert_eq!(
+            actual,
+            PartialConfig {
+                ticker: Some(30),
+                homes_include: Some(vec!["123".to_string(), "My Home".to_string()]),
+                listen_addresses: Some(vec![
+                    "[::]:9898".to_string(),
+                    "unix:/run/tado.sock".to_string()
+                ]),
+                web_config_file: Some("web.yml".to_string()),
+                basic_auth_users: Some(HashMap::from([(
+                    "prometheus".to_string(),
+                    "secret".to_string()
+                )])),
+                ..PartialConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_tls_flags_required_together() {
+        let actual = Cli::try_parse_from(["tado-exporter", "--tls-cert-file", "cert.pem"]);
+
+        assert!(actual.is_err());
+    }
+}
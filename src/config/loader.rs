@@ -1,71 +1,802 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use log::info;
+use serde_derive::Deserialize;
+
+use super::cli::Cli;
+use super::web::{self, WebConfig, WebConfigError};
+
+/// Address the HTTP server listens on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddress {
+    /// IPv4 or IPv6 socket address, e.g. `0.0.0.0:9898` or `[::]:9898`.
+    Tcp(SocketAddr),
+
+    /// Path of a unix domain socket, given as `unix:/path/to/socket`.
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_prefix("unix:") {
+            Some("") => Err("missing unix socket path".to_string()),
+            Some(path) => Ok(ListenAddress::Unix(PathBuf::from(path))),
+            None => value
+                .parse::<SocketAddr>()
+                .map(ListenAddress::Tcp)
+                .map_err(|e| format!("invalid listen address {value}: {e}")),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(addr) => write!(f, "{addr}"),
+            ListenAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Certificate and private key used to serve HTTPS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsConfig {
+    pub cert_file: String,
+    pub key_file: String,
+}
+
+/// Credentials required to access the exporter endpoints.
+///
+/// Secrets are either bcrypt hashes (`$2a$`, `$2b$` or `$2y$`) or plain text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WebAuth {
+    /// Basic auth passwords by user name.
+    pub basic_auth_users: HashMap<String, String>,
+    pub bearer_token: Option<String>,
+}
+
+impl WebAuth {
+    pub fn is_enabled(&self) -> bool {
+        !self.basic_auth_users.is_empty() || self.bearer_token.is_some()
+    }
+}
+
+/// Whether a configured secret is a bcrypt hash rather than a plain text value.
+pub fn is_bcrypt_hash(secret: &str) -> bool {
+    secret.starts_with("$2a$") || secret.starts_with("$2b$") || secret.starts_with("$2y$")
+}
+
+/// Where the tado° refresh token is persisted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenStorage {
+    /// Plain text token file.
+    File,
+
+    /// Memory only, nothing is persisted.
+    Memory,
+
+    /// Token file encrypted with a 256 bits key.
+    EncryptedFile([u8; 32]),
+}
+
+#[derive(Clone)]
 pub struct Config {
     pub ticker: u64,
+    /// Seconds to wait for in-flight work to complete on shutdown.
+    pub shutdown_timeout: u64,
     pub client_id: String,
     pub token_file: String,
+    pub token_storage: TokenStorage,
+    pub homes_include: Vec<String>,
+    pub homes_exclude: Vec<String>,
+    pub listen_addresses: Vec<ListenAddress>,
+    pub web_config_file: Option<String>,
+    pub tls: Option<TlsConfig>,
+    pub web_auth: WebAuth,
 }
 
 impl Config {
     pub fn print(&self) {
-        println!("--- tado° exporter configuration ---");
-        println!("Ticker seconds: {}", self.ticker);
-        println!("Client ID: {}", self.client_id);
-        println!("------------------------------------");
-    }
-}
-
-pub fn load() -> Config {
-    let config = Config {
-        ticker: match env::var("EXPORTER_TICKER") {
-            Ok(v) => v.parse::<u64>().unwrap(),
-            Err(_) => 60,
-        },
-        client_id: match env::var("EXPORTER_CLIENT_ID") {
-            Ok(v) => v,
-            Err(_) => "1bb50063-6b0c-4d11-bd99-387f4a91cc46".to_string(),
-        },
-        token_file: match env::var("EXPORTER_TOKEN_FILE") {
-            Ok(v) => v,
-            Err(_) => ".tado_token".to_string(),
-        },
+        info!("Ticker seconds: {}", self.ticker);
+        info!("Shutdown timeout seconds: {}", self.shutdown_timeout);
+        info!("Client ID: {}", self.client_id);
+        let token_storage = match self.token_storage {
+            TokenStorage::File => format!("file {}", self.token_file),
+            TokenStorage::Memory => "memory".to_string(),
+            TokenStorage::EncryptedFile(_) => format!("encrypted file {}", self.token_file),
+        };
+        info!("Token storage: {token_storage}");
+        if !self.homes_include.is_empty() {
+            info!("Included homes: {}", self.homes_include.join(", "));
+        }
+        if !self.homes_exclude.is_empty() {
+            info!("Excluded homes: {}", self.homes_exclude.join(", "));
+        }
+        let addresses: Vec<String> = self
+            .listen_addresses
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        info!("Listen addresses: {}", addresses.join(", "));
+        if let Some(web_config_file) = &self.web_config_file {
+            info!("Web config file: {web_config_file}");
+        }
+        if let Some(tls) = &self.tls {
+            info!("TLS certificate: {}", tls.cert_file);
+        }
+        if self.web_auth.is_enabled() {
+            let mut users: Vec<&str> = self
+                .web_auth
+                .basic_auth_users
+                .keys()
+                .map(|u| u.as_str())
+                .collect();
+            users.sort();
+            info!("Basic auth users: {}", users.join(", "));
+            info!("Bearer token: {}", self.web_auth.bearer_token.is_some());
+        }
+    }
+}
+
+/// Configuration Errors.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read.
+    Io(String, std::io::Error),
+
+    /// The config file is not valid YAML or contains unknown settings.
+    Parse(String, serde_yaml::Error),
+
+    /// The web config file could not be loaded.
+    WebConfig(String, WebConfigError),
+
+    /// A setting has an invalid value.
+    Invalid(&'static str, String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, inner) => write!(f, "unable to read config file {path}: {inner}"),
+            ConfigError::Parse(path, inner) => write!(f, "invalid config file {path}: {inner}"),
+            ConfigError::WebConfig(path, inner) => {
+                write!(f, "unable to load web config file {path}: {inner}")
+            }
+            ConfigError::Invalid(setting, message) => write!(f, "invalid {setting}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, ref inner) => Some(inner),
+            ConfigError::Parse(_, ref inner) => Some(inner),
+            ConfigError::WebConfig(_, ref inner) => Some(inner),
+            ConfigError::Invalid(_, _) => None,
+        }
+    }
+}
+
+/// Settings from a single source (config file or environment), unset values are `None`.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PartialConfig {
+    pub ticker: Option<u64>,
+    pub shutdown_timeout: Option<u64>,
+    pub client_id: Option<String>,
+    pub token_file: Option<String>,
+    pub token_store: Option<String>,
+    pub token_key: Option<String>,
+    pub token_key_file: Option<String>,
+    pub homes_include: Option<Vec<String>>,
+    pub homes_exclude: Option<Vec<String>>,
+    pub listen_addresses: Option<Vec<String>>,
+    pub web_config_file: Option<String>,
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub basic_auth_users: Option<HashMap<String, String>>,
+    pub bearer_token: Option<String>,
+    pub bearer_token_file: Option<String>,
+}
+
+impl PartialConfig {
+    /// Read settings from a YAML config file.
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+        serde_yaml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    /// Read settings from `EXPORTER_*` variables, provided by `var`.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        Ok(PartialConfig {
+            ticker: match var("EXPORTER_TICKER") {
+                Some(v) => Some(parse_seconds("EXPORTER_TICKER", &v)?),
+                None => None,
+            },
+            shutdown_timeout: match var("EXPORTER_SHUTDOWN_TIMEOUT") {
+                Some(v) => Some(parse_seconds("EXPORTER_SHUTDOWN_TIMEOUT", &v)?),
+                None => None,
+            },
+            client_id: var("EXPORTER_CLIENT_ID"),
+            token_file: var("EXPORTER_TOKEN_FILE"),
+            token_store: var("EXPORTER_TOKEN_STORE"),
+            token_key: var("EXPORTER_TOKEN_KEY"),
+            token_key_file: var("EXPORTER_TOKEN_KEY_FILE"),
+            homes_include: var("EXPORTER_HOMES_INCLUDE").map(|v| parse_list(&v)),
+            homes_exclude: var("EXPORTER_HOMES_EXCLUDE").map(|v| parse_list(&v)),
+            listen_addresses: var("EXPORTER_LISTEN_ADDRESSES").map(|v| parse_list(&v)),
+            web_config_file: var("EXPORTER_WEB_CONFIG_FILE"),
+            tls_cert_file: var("EXPORTER_TLS_CERT_FILE"),
+            tls_key_file: var("EXPORTER_TLS_KEY_FILE"),
+            basic_auth_users: match var("EXPORTER_BASIC_AUTH_USERS") {
+                Some(v) => Some(
+                    parse_list(&v)
+                        .iter()
+                        .map(|u| parse_user("EXPORTER_BASIC_AUTH_USERS", u))
+                        .collect::<Result<_, _>>()?,
+                ),
+                None => None,
+            },
+            bearer_token: var("EXPORTER_BEARER_TOKEN"),
+            bearer_token_file: var("EXPORTER_BEARER_TOKEN_FILE"),
+        })
+    }
+
+    /// Combine with settings from another source, which take precedence.
+    pub fn merge(self, other: PartialConfig) -> Self {
+        let (token_key, token_key_file) = secret_or(
+            (other.token_key, other.token_key_file),
+            (self.token_key, self.token_key_file),
+        );
+        let (bearer_token, bearer_token_file) = secret_or(
+            (other.bearer_token, other.bearer_token_file),
+            (self.bearer_token, self.bearer_token_file),
+        );
+
+        PartialConfig {
+            ticker: other.ticker.or(self.ticker),
+            shutdown_timeout: other.shutdown_timeout.or(self.shutdown_timeout),
+            client_id: other.client_id.or(self.client_id),
+            token_file: other.token_file.or(self.token_file),
+            token_store: other.token_store.or(self.token_store),
+            token_key,
+            token_key_file,
+            homes_include: other.homes_include.or(self.homes_include),
+            homes_exclude: other.homes_exclude.or(self.homes_exclude),
+            listen_addresses: other.listen_addresses.or(self.listen_addresses),
+            web_config_file: other.web_config_file.or(self.web_config_file),
+            tls_cert_file: other.tls_cert_file.or(self.tls_cert_file),
+            tls_key_file: other.tls_key_file.or(self.tls_key_file),
+            basic_auth_users: other.basic_auth_users.or(self.basic_auth_users),
+            bearer_token,
+            bearer_token_file,
+        }
+    }
+
+    /// Validate the settings and fill in defaults.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        // The web config file only provides defaults for the TLS and basic auth settings.
+        let web_config = match &self.web_config_file {
+            Some(path) => web::load(path).map_err(|e| ConfigError::WebConfig(path.clone(), e))?,
+            None => WebConfig::default(),
+        };
+
+        let ticker = self.ticker.unwrap_or(60);
+        if ticker == 0 {
+            return Err(ConfigError::Invalid(
+                "ticker",
+                "must be at least 1 second".to_string(),
+            ));
+        }
+
+        let client_id = self
+            .client_id
+            .unwrap_or_else(|| "1bb50063-6b0c-4d11-bd99-387f4a91cc46".to_string());
+        if client_id.trim().is_empty() {
+            return Err(ConfigError::Invalid(
+                "client_id",
+                "must not be empty".to_string(),
+            ));
+        }
+
+        let token_file = self.token_file.unwrap_or_else(|| ".tado_token".to_string());
+        if token_file.trim().is_empty() {
+            return Err(ConfigError::Invalid(
+                "token_file",
+                "must not be empty".to_string(),
+            ));
+        }
+
+        let token_storage = match self.token_store.as_deref().unwrap_or("file") {
+            "file" => TokenStorage::File,
+            "memory" => TokenStorage::Memory,
+            "encrypted_file" => {
+                TokenStorage::EncryptedFile(token_key(self.token_key, self.token_key_file)?)
+            }
+            other => {
+                return Err(ConfigError::Invalid(
+                    "token_store",
+                    format!("{other:?} is not one of file, memory or encrypted_file"),
+                ))
+            }
+        };
+
+        let listen_addresses = match self.listen_addresses {
+            Some(addresses) => addresses
+                .iter()
+                .map(|a| a.parse())
+                .collect::<Result<Vec<ListenAddress>, _>>()
+                .map_err(|e| ConfigError::Invalid("listen_addresses", e))?,
+            None => vec![ListenAddress::Tcp(([0, 0, 0, 0], 9898).into())],
+        };
+        if listen_addresses.is_empty() {
+            return Err(ConfigError::Invalid(
+                "listen_addresses",
+                "at least one address is required".to_string(),
+            ));
+        }
+
+        let tls = match (self.tls_cert_file, self.tls_key_file) {
+            (Some(cert_file), Some(key_file)) => Some(TlsConfig {
+                cert_file,
+                key_file,
+            }),
+            (None, None) => web_config.tls,
+            _ => {
+                return Err(ConfigError::Invalid(
+                    "tls",
+                    "tls_cert_file and tls_key_file must be set together".to_string(),
+                ))
+            }
+        };
+
+        let bearer_token = read_secret("bearer_token", self.bearer_token, self.bearer_token_file)?;
+
+        Ok(Config {
+            ticker,
+            shutdown_timeout: self.shutdown_timeout.unwrap_or(10),
+            client_id,
+            token_file,
+            token_storage,
+            homes_include: self.homes_include.unwrap_or_default(),
+            homes_exclude: self.homes_exclude.unwrap_or_default(),
+            listen_addresses,
+            web_config_file: self.web_config_file,
+            tls,
+            web_auth: WebAuth {
+                basic_auth_users: self
+                    .basic_auth_users
+                    .unwrap_or(web_config.web_auth.basic_auth_users),
+                bearer_token,
+            },
+        })
+    }
+}
+
+/// Load the configuration from the config file, if any, the environment and the command line.
+///
+/// Command line flags take precedence over environment variables, which take precedence
+/// over the config file.
+pub fn load(cli: Cli) -> Result<Config, ConfigError> {
+    let file = match cli
+        .config
+        .clone()
+        .or_else(|| env::var("EXPORTER_CONFIG_FILE").ok())
+    {
+        Some(path) => PartialConfig::from_file(&path)?,
+        None => PartialConfig::default(),
     };
+    let environment = PartialConfig::from_env(|key| env::var(key).ok())?;
+    let flags = cli.into_partial()?;
+
+    let config = file.merge(environment).merge(flags).build()?;
 
     config.print();
 
-    config
+    Ok(config)
+}
+
+/// Read the token encryption key, given directly or in a key file, as base64 encoded 32 bytes.
+fn token_key(key: Option<String>, key_file: Option<String>) -> Result<[u8; 32], ConfigError> {
+    let key = read_secret("token_key", key, key_file)?.ok_or_else(|| {
+        ConfigError::Invalid(
+            "token_key",
+            "the encrypted_file token store requires either token_key or token_key_file"
+                .to_string(),
+        )
+    })?;
+
+    let key = BASE64
+        .decode(key.trim())
+        .map_err(|e| ConfigError::Invalid("token_key", format!("not base64 encoded: {e}")))?;
+
+    key.try_into().map_err(|key: Vec<u8>| {
+        ConfigError::Invalid(
+            "token_key",
+            format!("must be 32 bytes long, got {}", key.len()),
+        )
+    })
+}
+
+/// Pick a secret and its file from the source setting either of them, so that a source
+/// setting the file overrides a secret set by a source of lower precedence, and vice versa.
+fn secret_or<T>(
+    high: (Option<T>, Option<T>),
+    low: (Option<T>, Option<T>),
+) -> (Option<T>, Option<T>) {
+    if high.0.is_some() || high.1.is_some() {
+        high
+    } else {
+        low
+    }
+}
+
+/// Read a secret given either directly or in a file, which keeps it out of the process list.
+fn read_secret(
+    setting: &'static str,
+    value: Option<String>,
+    file: Option<String>,
+) -> Result<Option<String>, ConfigError> {
+    let secret = match (value, file) {
+        (None, None) => return Ok(None),
+        (Some(value), None) => value,
+        (None, Some(path)) => fs::read_to_string(&path)
+            .map_err(|e| ConfigError::Invalid(setting, format!("unable to read {path}: {e}")))?
+            .trim()
+            .to_string(),
+        (Some(_), Some(_)) => {
+            return Err(ConfigError::Invalid(
+                setting,
+                format!("set either {setting} or {setting}_file, not both"),
+            ))
+        }
+    };
+
+    if secret.is_empty() {
+        return Err(ConfigError::Invalid(
+            setting,
+            "must not be empty".to_string(),
+        ));
+    }
+
+    Ok(Some(secret))
+}
+
+/// Parse a number of seconds.
+fn parse_seconds(setting: &'static str, value: &str) -> Result<u64, ConfigError> {
+    value
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| ConfigError::Invalid(setting, format!("{value:?} is not a number: {e}")))
+}
+
+/// Parse a comma separated list of values, ignoring blank entries.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect()
+}
+
+/// Parse a `user:password` pair.
+pub(super) fn parse_user(
+    setting: &'static str,
+    value: &str,
+) -> Result<(String, String), ConfigError> {
+    match value.split_once(':') {
+        Some((user, password)) => Ok((user.to_string(), password.to_string())),
+        None => Err(ConfigError::Invalid(
+            setting,
+            format!("{value:?} is not a user:password pair"),
+        )),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use rstest::rstest;
+
     #[test]
     fn test_config_load() {
         // Given no env variable are set
         env::remove_var("EXPORTER_TICKER");
+        env::remove_var("EXPORTER_SHUTDOWN_TIMEOUT");
         env::remove_var("EXPORTER_CLIENT_ID");
         env::remove_var("EXPORTER_TOKEN_FILE");
+        env::remove_var("EXPORTER_TOKEN_STORE");
+        env::remove_var("EXPORTER_TOKEN_KEY");
+        env::remove_var("EXPORTER_TOKEN_KEY_FILE");
+        env::remove_var("EXPORTER_HOMES_INCLUDE");
+        env::remove_var("EXPORTER_HOMES_EXCLUDE");
+        env::remove_var("EXPORTER_LISTEN_ADDRESSES");
+        env::remove_var("EXPORTER_TLS_CERT_FILE");
+        env::remove_var("EXPORTER_TLS_KEY_FILE");
+        env::remove_var("EXPORTER_BASIC_AUTH_USERS");
+        env::remove_var("EXPORTER_BEARER_TOKEN");
+        env::remove_var("EXPORTER_BEARER_TOKEN_FILE");
+        env::remove_var("EXPORTER_WEB_CONFIG_FILE");
+        env::remove_var("EXPORTER_CONFIG_FILE");
 
         // when
-        let config = load();
+        let config = load(Cli::default()).unwrap();
 
         // then we should load default values
         assert_eq!(config.ticker, 60);
+        assert_eq!(config.shutdown_timeout, 10);
         assert_eq!(config.client_id, "1bb50063-6b0c-4d11-bd99-387f4a91cc46");
         assert_eq!(config.token_file, ".tado_token");
+        assert_eq!(config.token_storage, TokenStorage::File);
+        assert!(config.homes_include.is_empty());
+        assert!(config.homes_exclude.is_empty());
+        assert_eq!(
+            config.listen_addresses,
+            vec![ListenAddress::Tcp("0.0.0.0:9898".parse().unwrap())]
+        );
+        assert_eq!(config.tls, None);
+        assert!(!config.web_auth.is_enabled());
 
         // given the following environment variable values
         env::set_var("EXPORTER_TICKER", "30");
+        env::set_var("EXPORTER_SHUTDOWN_TIMEOUT", "5");
         env::set_var("EXPORTER_CLIENT_ID", "client-123");
         env::set_var("EXPORTER_TOKEN_FILE", "my_token_file");
+        env::set_var("EXPORTER_TOKEN_STORE", "encrypted_file");
+        env::set_var("EXPORTER_TOKEN_KEY", BASE64.encode([1; 32]));
+        env::set_var("EXPORTER_HOMES_INCLUDE", "123, My Home");
+        env::set_var("EXPORTER_HOMES_EXCLUDE", "456,");
+        env::set_var(
+            "EXPORTER_LISTEN_ADDRESSES",
+            "127.0.0.1:9898, [::1]:9899, unix:/run/tado.sock",
+        );
+        env::set_var("EXPORTER_TLS_CERT_FILE", "cert.pem");
+        env::set_var("EXPORTER_TLS_KEY_FILE", "key.pem");
+        env::set_var(
+            "EXPORTER_BASIC_AUTH_USERS",
+            "prometheus:$2y$10$X0h1wzZKZ0JxXWPQnS2/0O3u5TBW6fvUDTdIZ2wRxdyUq9DcbFkha",
+        );
+        env::set_var("EXPORTER_BEARER_TOKEN", "secret");
 
         // when
-        let config = load();
+        let config = load(Cli::default()).unwrap();
 
         // then we should have these values set
         assert_eq!(config.ticker, 30);
+        assert_eq!(config.shutdown_timeout, 5);
         assert_eq!(config.client_id, "client-123");
         assert_eq!(config.token_file, "my_token_file");
+        assert_eq!(config.token_storage, TokenStorage::EncryptedFile([1; 32]));
+        assert_eq!(config.homes_include, vec!["123", "My Home"]);
+        assert_eq!(config.homes_exclude, vec!["456"]);
+        assert_eq!(
+            config.listen_addresses,
+            vec![
+                ListenAddress::Tcp("127.0.0.1:9898".parse().unwrap()),
+                ListenAddress::Tcp("[::1]:9899".parse().unwrap()),
+                ListenAddress::Unix(PathBuf::from("/run/tado.sock")),
+            ]
+        );
+        assert_eq!(
+            config.tls,
+            Some(TlsConfig {
+                cert_file: "cert.pem".to_string(),
+                key_file: "key.pem".to_string(),
+            })
+        );
+        assert_eq!(
+            config.web_auth,
+            WebAuth {
+                basic_auth_users: HashMap::from([(
+                    "prometheus".to_string(),
+                    "$2y$10$X0h1wzZKZ0JxXWPQnS2/0O3u5TBW6fvUDTdIZ2wRxdyUq9DcbFkha".to_string()
+                )]),
+                bearer_token: Some("secret".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_file_overridden_by_env() {
+        /*
+        GIVEN settings from a config file and from the environment
+        WHEN the configuration is built
+        THEN environment values take precedence over file values
+        */
+
+        // GIVEN
+        let file: PartialConfig = serde_yaml::from_str(
+            r#"
+ticker: 30
+client_id: client-file
+homes_include: [Home]
+listen_addresses: ["[::]:9898"]
+basic_auth_users:
+  prometheus: secret
+"#,
+        )
+        .unwrap();
+        let environment = PartialConfig::from_env(|key| match key {
+            "EXPORTER_TICKER" => Some("15".to_string()),
+            "EXPORTER_TOKEN_FILE" => Some("/data/token".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        // WHEN
+        let config = file.merge(environment).build().unwrap();
+
+        // THEN
+        assert_eq!(config.ticker, 15);
+        assert_eq!(config.client_id, "client-file");
+        assert_eq!(config.token_file, "/data/token");
+        assert_eq!(config.homes_include, vec!["Home"]);
+        assert_eq!(
+            config.listen_addresses,
+            vec![ListenAddress::Tcp("[::]:9898".parse().unwrap())]
+        );
+        assert_eq!(
+            config.web_auth.basic_auth_users,
+            HashMap::from([("prometheus".to_string(), "secret".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_bearer_token_file_overrides_token() {
+        /*
+        GIVEN a bearer token in the config file and a bearer token file in the environment
+        WHEN the configuration is built
+        THEN the token is read from the file of the source with the highest precedence
+        */
+
+        // GIVEN
+        let path =
+            std::env::temp_dir().join(format!("tado-exporter-{}-bearer", std::process::id()));
+        fs::write(&path, "from-file\n").unwrap();
+
+        let file = PartialConfig {
+            bearer_token: Some("from-config".to_string()),
+            ..PartialConfig::default()
+        };
+        let environment = PartialConfig {
+            bearer_token_file: Some(path.to_string_lossy().into_owned()),
+            ..PartialConfig::default()
+        };
+
+        // WHEN
+        let config = file.merge(environment).build().unwrap();
+
+        // THEN
+        assert_eq!(config.web_auth.bearer_token, Some("from-file".to_string()));
+    }
+
+    #[test]
+    fn test_config_file_unknown_setting() {
+        let actual = serde_yaml::from_str::<PartialConfig>("tickr: 30");
+
+        assert!(actual.is_err());
+    }
+
+    #[rstest]
+    #[case(
+        "EXPORTER_TICKER",
+        "60s",
+        "invalid EXPORTER_TICKER: \"60s\" is not a number"
+    )]
+    #[case("EXPORTER_TICKER", "0", "invalid ticker: must be at least 1 second")]
+    #[case("EXPORTER_CLIENT_ID", " ", "invalid client_id: must not be empty")]
+    #[case(
+        "EXPORTER_LISTEN_ADDRESSES",
+        "localhost",
+        "invalid listen_addresses: invalid listen address localhost"
+    )]
+    #[case(
+        "EXPORTER_LISTEN_ADDRESSES",
+        ",",
+        "invalid listen_addresses: at least one"
+    )]
+    #[case(
+        "EXPORTER_TLS_CERT_FILE",
+        "cert.pem",
+        "invalid tls: tls_cert_file and tls_key_file"
+    )]
+    #[case(
+        "EXPORTER_BASIC_AUTH_USERS",
+        "prometheus",
+        "invalid EXPORTER_BASIC_AUTH_USERS"
+    )]
+    #[case("EXPORTER_BEARER_TOKEN", "", "invalid bearer_token: must not be empty")]
+    #[case(
+        "EXPORTER_BEARER_TOKEN_FILE",
+        "/nonexistent/token",
+        "invalid bearer_token: unable to read /nonexistent/token"
+    )]
+    fn test_config_invalid(#[case] key: &str, #[case] value: &str, #[case] expected: &str) {
+        /*
+        GIVEN an invalid environment variable
+        WHEN the configuration is built
+        THEN a readable error is returned
+        */
+
+        // GIVEN
+        let var = |k: &str| (k == key).then(|| value.to_string());
+
+        // WHEN
+        let actual = PartialConfig::from_env(var).and_then(|c| c.build());
+
+        // THEN
+        let error = actual.err().unwrap().to_string();
+        assert!(error.starts_with(expected), "{}", error);
+    }
+
+    #[test]
+    fn test_token_key() {
+        assert_eq!(
+            token_key(Some(BASE64.encode([1; 32])), None).unwrap(),
+            [1; 32]
+        );
+        assert_eq!(
+            token_key(Some("not base64!".to_string()), None)
+                .unwrap_err()
+                .to_string(),
+            "invalid token_key: not base64 encoded: Invalid symbol 32, offset 3."
+        );
+        assert_eq!(
+            token_key(Some(BASE64.encode([1; 16])), None)
+                .unwrap_err()
+                .to_string(),
+            "invalid token_key: must be 32 bytes long, got 16"
+        );
+        assert!(token_key(None, Some("/nonexistent/key".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_token_key_file_overrides_key() {
+        /*
+        GIVEN a token key in the config file and a token key file in the environment
+        WHEN the configuration is built
+        THEN the key is read from the file of the source with the highest precedence
+        */
+
+        // GIVEN
+        let path =
+            std::env::temp_dir().join(format!("tado-exporter-{}-token-key", std::process::id()));
+        fs::write(&path, BASE64.encode([2; 32])).unwrap();
+
+        let file = PartialConfig {
+            token_store: Some("encrypted_file".to_string()),
+            token_key: Some(BASE64.encode([1; 32])),
+            ..PartialConfig::default()
+        };
+        let environment = PartialConfig {
+            token_key_file: Some(path.to_string_lossy().into_owned()),
+            ..PartialConfig::default()
+        };
+
+        // WHEN
+        let config = file.merge(environment).build().unwrap();
+
+        // THEN
+        assert_eq!(config.token_storage, TokenStorage::EncryptedFile([2; 32]));
+    }
+
+    #[test]
+    fn test_listen_address_invalid() {
+        assert!("localhost".parse::<ListenAddress>().is_err());
+        assert!("unix:".parse::<ListenAddress>().is_err());
     }
 }
@@ -1 +1,3 @@
+pub mod cli;
 pub mod loader;
+pub mod web;
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde_derive::Deserialize;
+
+use super::loader::{is_bcrypt_hash, TlsConfig, WebAuth};
+
+/// Web configuration file, in the format used by the Prometheus exporter-toolkit.
+///
+/// See https://github.com/prometheus/exporter-toolkit/blob/master/docs/web-configuration.md
+#[derive(Deserialize, Debug, Default)]
+struct WebConfigFile {
+    tls_server_config: Option<TlsServerConfig>,
+    #[serde(default)]
+    basic_auth_users: HashMap<String, String>,
+    #[serde(flatten)]
+    unsupported: HashMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TlsServerConfig {
+    cert_file: String,
+    key_file: String,
+    #[serde(flatten)]
+    unsupported: HashMap<String, serde_yaml::Value>,
+}
+
+/// Settings read from a web configuration file.
+#[derive(Debug, Default, PartialEq)]
+pub struct WebConfig {
+    pub tls: Option<TlsConfig>,
+    pub web_auth: WebAuth,
+}
+
+/// Web configuration file Errors.
+#[derive(Debug)]
+pub enum WebConfigError {
+    /// The file could not be read.
+    Io(std::io::Error),
+
+    /// The file is not valid YAML or does not match the expected format.
+    Parse(serde_yaml::Error),
+
+    /// The file uses a setting the exporter does not implement.
+    Unsupported(String),
+
+    /// The password of a basic auth user is not a bcrypt hash.
+    PlainPassword(String),
+}
+
+impl std::fmt::Display for WebConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebConfigError::Io(inner) => std::fmt::Display::fmt(inner, f),
+            WebConfigError::Parse(inner) => std::fmt::Display::fmt(inner, f),
+            WebConfigError::Unsupported(key) => {
+                write!(f, "setting {key} is not supported by the exporter")
+            }
+            WebConfigError::PlainPassword(user) => {
+                write!(
+                    f,
+                    "password of basic auth user {user} must be a bcrypt hash"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for WebConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WebConfigError::Io(ref inner) => Some(inner),
+            WebConfigError::Parse(ref inner) => Some(inner),
+            WebConfigError::Unsupported(_) => None,
+            WebConfigError::PlainPassword(_) => None,
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<WebConfig, WebConfigError> {
+    let content = fs::read_to_string(path).map_err(WebConfigError::Io)?;
+
+    parse(
+        &content,
+        Path::new(path).parent().unwrap_or_else(|| Path::new("")),
+    )
+}
+
+/// Parse a web configuration, resolving relative file paths against `base_dir`
+/// like the exporter-toolkit does.
+///
+/// Settings the exporter does not implement are refused rather than ignored, so that
+/// e.g. a required client certificate is never silently dropped.
+fn parse(content: &str, base_dir: &Path) -> Result<WebConfig, WebConfigError> {
+    let file: WebConfigFile = serde_yaml::from_str(content).map_err(WebConfigError::Parse)?;
+
+    if let Some(key) = file.unsupported.keys().min() {
+        return Err(WebConfigError::Unsupported(key.clone()));
+    }
+
+    let tls = match file.tls_server_config {
+        Some(tls) => {
+            if let Some(key) = tls.unsupported.keys().min() {
+                return Err(WebConfigError::Unsupported(format!(
+                    "tls_server_config.{key}"
+                )));
+            }
+
+            Some(TlsConfig {
+                cert_file: resolve(base_dir, &tls.cert_file),
+                key_file: resolve(base_dir, &tls.key_file),
+            })
+        }
+        None => None,
+    };
+
+    // The exporter-toolkit format only allows bcrypt hashed passwords.
+    let plain_password_user = file
+        .basic_auth_users
+        .iter()
+        .filter(|(_, password)| !is_bcrypt_hash(password))
+        .map(|(user, _)| user)
+        .min();
+    if let Some(user) = plain_password_user {
+        return Err(WebConfigError::PlainPassword(user.clone()));
+    }
+
+    Ok(WebConfig {
+        tls,
+        web_auth: WebAuth {
+            basic_auth_users: file.basic_auth_users,
+            bearer_token: None,
+        },
+    })
+}
+
+fn resolve(base_dir: &Path, file: &str) -> String {
+    base_dir.join(file).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[test]
+    fn test_parse() {
+        /*
+        GIVEN an exporter-toolkit web config with TLS and basic auth users
+        WHEN it is parsed
+        THEN relative files are resolved against the config directory
+        */
+
+        // GIVEN
+        let content = r#"
+tls_server_config:
+  cert_file: tls/cert.pem
+  key_file: /etc/ssl/key.pem
+basic_auth_users:
+  prometheus: $2y$10$X0h1wzZKZ0JxXWPQnS2/0O3u5TBW6fvUDTdIZ2wRxdyUq9DcbFkha
+"#;
+
+        // WHEN
+        let actual = parse(content, Path::new("/etc/tado")).unwrap();
+
+        // THEN
+        assert_eq!(
+            actual,
+            WebConfig {
+                tls: Some(TlsConfig {
+                    cert_file: "/etc/tado/tls/cert.pem".to_string(),
+                    key_file: "/etc/ssl/key.pem".to_string(),
+                }),
+                web_auth: WebAuth {
+                    basic_auth_users: HashMap::from([(
+                        "prometheus".to_string(),
+                        "$2y$10$X0h1wzZKZ0JxXWPQnS2/0O3u5TBW6fvUDTdIZ2wRxdyUq9DcbFkha".to_string()
+                    )]),
+                    bearer_token: None,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let actual = parse("{}", Path::new("")).unwrap();
+
+        assert_eq!(actual, WebConfig::default());
+    }
+
+    #[rstest]
+    #[case(
+        "tls_server_config:\n  cert_file: cert.pem\n  key_file: key.pem\n  client_auth_type: RequireAndVerifyClientCert\n",
+        "tls_server_config.client_auth_type"
+    )]
+    #[case(
+        "tls_server_config:\n  cert_file: cert.pem\n  key_file: key.pem\n  min_version: TLS12\n",
+        "tls_server_config.min_version"
+    )]
+    #[case("http_server_config:\n  http2: false\n", "http_server_config")]
+    fn test_parse_unsupported(#[case] content: &str, #[case] expected: &str) {
+        /*
+        GIVEN a web config with a setting the exporter does not implement
+        WHEN it is parsed
+        THEN it is refused instead of being served without the setting
+        */
+
+        // WHEN
+        let actual = parse(content, Path::new(""));
+
+        // THEN
+        assert!(matches!(actual, Err(WebConfigError::Unsupported(key)) if key == expected));
+    }
+
+    #[test]
+    fn test_parse_plain_password() {
+        let actual = parse("basic_auth_users:\n  prometheus: secret\n", Path::new(""));
+
+        assert!(matches!(actual, Err(WebConfigError::PlainPassword(user)) if user == "prometheus"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let actual = parse("tls_server_config:\n  cert_file: cert.pem\n", Path::new(""));
+
+        assert!(matches!(actual, Err(WebConfigError::Parse(_))));
+    }
+}
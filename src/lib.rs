@@ -0,0 +1,4 @@
+#[macro_use]
+extern crate prometheus;
+
+pub mod tado;
@@ -1,70 +1,219 @@
-#[macro_use]
-extern crate prometheus;
-
 mod config;
-mod tado;
+mod server;
 
 use env_logger::{Builder as LoggerBuilder, Env};
-use hyper::{service::make_service_fn, service::service_fn, Server};
-use hyper::{Body, Request, Response};
-use log::{error, info};
-use std::convert::Infallible;
-use std::time::Duration;
-
-use config::loader as config_loader;
+use log::{error, info, warn};
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use clap::Parser;
+use config::cli::Cli;
+use config::loader::{self as config_loader, TokenStorage};
 use tado::client::Client as TadoClient;
+use tado::error::{AuthError, HomeError};
 use tado::metrics;
+use tado::model::HomeFilter;
+use tado::token_store::{EncryptedFileTokenStore, FileTokenStore, MemoryTokenStore, TokenStore};
+use tado_exporter::tado;
 
 #[tokio::main]
 async fn main() {
     LoggerBuilder::from_env(Env::default().default_filter_or("info")).init();
 
-    let config = config_loader::load();
+    let config = match config_loader::load(Cli::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
 
-    // start ticker
-    run_ticker(config);
+    let (shutdown_sender, shutdown) = watch::channel(false);
 
-    // set up http server
-    let addr = ([0, 0, 0, 0], 9898).into();
-    info!("starting tado° exporter on address: {addr:?}");
+    // start ticker
+    let mut ticker = run_ticker(config.clone(), shutdown.clone());
 
-    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(route)) });
+    // start HTTP server
+    let mut server_shutdown = shutdown.clone();
+    let server = server::serve(
+        &config.listen_addresses,
+        config.tls.as_ref(),
+        config.web_auth.clone(),
+        async move {
+            let _ = server_shutdown.changed().await;
+        },
+    );
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => {
+            if let Err(e) = result {
+                error!("a server error occurred: {e}");
+            }
+            return;
+        }
+        // The ticker only stops on its own when the configured homes are invalid.
+        result = &mut ticker => {
+            if let Ok(Err(e)) = result {
+                error!("invalid home selection: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ = shutdown_signal() => {}
+    }
 
-    let server = Server::bind(&addr).serve(make_svc);
+    info!(
+        "shutting down, waiting up to {} seconds",
+        config.shutdown_timeout
+    );
+    let _ = shutdown_sender.send(true);
 
-    // start HTTP server
-    if let Err(e) = server.await {
-        error!("a server error occurred: {e}");
+    // Let the ticker finish the current scrape, including any token write, and drain HTTP connections.
+    let drained = async {
+        if let Err(e) = server.await {
+            error!("a server error occurred: {e}");
+        }
+        let _ = ticker.await;
+    };
+    match tokio::time::timeout(Duration::from_secs(config.shutdown_timeout), drained).await {
+        Ok(()) => info!("shutdown completed"),
+        Err(_) => warn!("shutdown timed out, exiting"),
     }
 }
 
-async fn route(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    info!("{}", req.uri());
+/// Wait for SIGTERM or SIGINT.
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!("unable to listen for SIGTERM, only SIGINT stops the exporter: {e}");
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                error!("unable to listen for SIGINT: {e}");
+                std::future::pending::<()>().await;
+            }
+            info!("received SIGINT");
+            return;
+        }
+    };
 
-    metrics::renderer(req).await
+    tokio::select! {
+        _ = terminate.recv() => info!("received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => info!("received SIGINT"),
+    }
 }
 
-fn run_ticker(config: config_loader::Config) {
+/// Scrape the tado° API on every tick until shutdown.
+///
+/// Returns an error when a configured home is not linked to the account.
+fn run_ticker(
+    config: config_loader::Config,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<Result<(), HomeError>> {
     tokio::spawn(async move {
-        let mut tado_client = TadoClient::new(config.client_id, config.token_file);
-        if let Err(e) = tado_client.authenticate().await {
-            error!("unable to authenticate: {e}");
-            return;
+        let home_filter = HomeFilter {
+            include: config.homes_include,
+            exclude: config.homes_exclude,
+        };
+        let token_store: Box<dyn TokenStore> = match config.token_storage {
+            TokenStorage::File => Box::new(FileTokenStore::new(config.token_file)),
+            TokenStorage::Memory => Box::new(MemoryTokenStore::default()),
+            TokenStorage::EncryptedFile(key) => {
+                Box::new(EncryptedFileTokenStore::new(config.token_file, key))
+            }
+        };
+        let mut tado_client = TadoClient::new(config.client_id, token_store, home_filter);
+        loop {
+            // The device authentication flow can wait for minutes, don't hold the shutdown.
+            let authenticated = tokio::select! {
+                result = tado_client.authenticate() => result,
+                _ = shutdown.changed() => return Ok(()),
+            };
+            match authenticated {
+                Ok(()) => break,
+                // The tokens are valid, storing them is retried on the next tick.
+                Err(e @ AuthError::Storage(_)) => {
+                    error!("{e}, will retry");
+                    break;
+                }
+                Err(e) => error!("unable to authenticate: {e}, will retry"),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(config.ticker)) => {}
+                _ = shutdown.changed() => return Ok(()),
+            }
         }
 
         // Use a ticker instead of sleeping within the loop.
         // This prevents drift as the ticker keeps counting down during refresh, unlike sleep.
         let mut ticker = tokio::time::interval(Duration::from_secs(config.ticker));
+        // Don't catch up on the ticks missed while waiting for a login.
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         loop {
-            ticker.tick().await;
-            if let Err(e) = tado_client.refresh_authentication().await {
-                error!("unable to refresh authentication tokens: {e}");
-                continue;
+            // Only stop between scrapes, so that a token refresh is never interrupted.
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.changed() => {
+                    info!("ticker stopped");
+                    return Ok(());
+                }
+            }
+            let started = Instant::now();
+
+            match tado_client.refresh_authentication().await {
+                Ok(()) => {}
+                // The tokens are valid, storing them is retried on the next tick.
+                Err(e @ AuthError::Storage(_)) => error!("{e}, will retry"),
+                // The refresh token is no longer valid or missing, a new login is required.
+                Err(e @ AuthError::InvalidGrant) | Err(e @ AuthError::MissingRefreshToken) => {
+                    warn!("{e}, starting the device authentication flow");
+
+                    let reauthenticated = tokio::select! {
+                        result = tado_client.reauthenticate() => result,
+                        _ = shutdown.changed() => return Ok(()),
+                    };
+                    match reauthenticated {
+                        Ok(()) => {}
+                        Err(e @ AuthError::Storage(_)) => error!("{e}, will retry"),
+                        Err(e) => {
+                            error!("unable to authenticate: {e}");
+                            metrics::set_scrape_result(&["auth"], started.elapsed());
+                            continue;
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("unable to refresh authentication tokens: {e}");
+                    metrics::set_scrape_result(&["auth"], started.elapsed());
+                    continue;
+                }
             }
 
-            metrics::set_zones(tado_client.retrieve_zones().await);
-            metrics::set_weather(tado_client.retrieve_weather().await);
+            // Homes are retrieved once, on the first tick a misconfigured home is reported.
+            // Other errors are retried on the next tick.
+            match tado_client.retrieve_homes().await {
+                Ok(()) => {}
+                Err(e @ HomeError::NotFound(_)) => return Err(e),
+                Err(e) => {
+                    error!("unable to retrieve homes: {e}");
+                    metrics::set_scrape_result(&["me"], started.elapsed());
+                    continue;
+                }
+            }
+
+            let zones = tado_client.retrieve_zones().await;
+            metrics::set_zones(zones.values, &zones.failed_homes);
+            let devices = tado_client.retrieve_devices().await;
+            metrics::set_devices(devices.values, &devices.failed_homes);
+            let weather = tado_client.retrieve_weather().await;
+            metrics::set_weather(weather.values, &weather.failed_homes);
+
+            metrics::set_scrape_result(&tado_client.take_scrape_errors(), started.elapsed());
         }
-    });
+    })
 }
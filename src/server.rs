@@ -0,0 +1,458 @@
+use std::convert::Infallible;
+use std::fs;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use hyper::server::accept;
+use hyper::{service::make_service_fn, service::service_fn, Server};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{error, info, warn};
+use openssl::error::ErrorStack;
+use openssl::memcmp;
+use openssl::ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::mpsc;
+use tokio_openssl::SslStream;
+
+use crate::config::loader::{is_bcrypt_hash, ListenAddress, TlsConfig, WebAuth};
+use tado_exporter::tado::metrics;
+use tado_exporter::tado::status;
+
+/// Delay before accepting connections again after an accept error.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Connection accepted on any of the listeners, plain or TLS.
+trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+/// Server Errors.
+#[derive(Debug)]
+pub enum ServerError {
+    /// Binding a listen address failed.
+    Bind(ListenAddress, io::Error),
+
+    /// The TLS certificate or private key could not be loaded.
+    Tls(ErrorStack),
+
+    /// The HTTP server failed.
+    Http(hyper::Error),
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::Bind(address, inner) => write!(f, "unable to bind {address}: {inner}"),
+            ServerError::Tls(inner) => write!(f, "unable to load TLS configuration: {inner}"),
+            ServerError::Http(inner) => std::fmt::Display::fmt(inner, f),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServerError::Bind(_, ref inner) => Some(inner),
+            ServerError::Tls(ref inner) => Some(inner),
+            ServerError::Http(ref inner) => Some(inner),
+        }
+    }
+}
+
+/// Serve the exporter endpoints on all the given addresses, over HTTPS when TLS is configured.
+///
+/// Once `shutdown` completes, no new connections are accepted and the server returns
+/// when the open connections are drained.
+pub async fn serve(
+    addresses: &[ListenAddress],
+    tls: Option<&TlsConfig>,
+    web_auth: WebAuth,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), ServerError> {
+    let acceptor = match tls {
+        Some(tls) => Some(tls_acceptor(tls).map_err(ServerError::Tls)?),
+        None => None,
+    };
+
+    // Every listener forwards its connections to a single hyper server.
+    let (sender, mut receiver) = mpsc::channel::<Box<dyn Connection>>(64);
+
+    for address in addresses {
+        let listener = bind(address)
+            .await
+            .map_err(|e| ServerError::Bind(address.clone(), e))?;
+
+        let scheme = if acceptor.is_some() { "https" } else { "http" };
+        info!("starting tado° exporter on address: {address} ({scheme})");
+
+        tokio::spawn(listener.run(acceptor.clone(), sender.clone()));
+    }
+    drop(sender);
+
+    let incoming = accept::poll_fn(move |cx| {
+        receiver
+            .poll_recv(cx)
+            .map(|connection| connection.map(Ok::<_, Infallible>))
+    });
+
+    let web_auth = Arc::new(web_auth);
+    let make_svc = make_service_fn(move |_conn| {
+        let web_auth = web_auth.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| route(req, web_auth.clone()))) }
+    });
+
+    Server::builder(incoming)
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(ServerError::Http)
+}
+
+async fn route(req: Request<Body>, web_auth: Arc<WebAuth>) -> Result<Response<Body>, Infallible> {
+    info!("{}", req.uri());
+
+    match req.uri().path() {
+        // Probes stay reachable without credentials.
+        "/healthz" => return status::liveness(req).await,
+        "/readyz" => return status::readiness(req).await,
+        _ => {}
+    }
+
+    if web_auth.is_enabled() {
+        let authorization = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+
+        if !is_authorized(&web_auth, authorization).await {
+            return Ok(unauthorized(&web_auth));
+        }
+    }
+
+    match req.uri().path() {
+        "/" | "/status" => status::renderer(req).await,
+        "/metrics" => metrics::renderer(req).await,
+        _ => status::not_found(req).await,
+    }
+}
+
+/// Check the `Authorization` header against the configured users and bearer token.
+async fn is_authorized(web_auth: &WebAuth, authorization: Option<&str>) -> bool {
+    let Some((scheme, credentials)) = authorization.and_then(|v| v.split_once(' ')) else {
+        return false;
+    };
+
+    if scheme.eq_ignore_ascii_case("basic") {
+        let Some(decoded) = BASE64
+            .decode(credentials.trim())
+            .ok()
+            .and_then(|d| String::from_utf8(d).ok())
+        else {
+            return false;
+        };
+
+        return match decoded.split_once(':') {
+            Some((user, password)) => match web_auth.basic_auth_users.get(user) {
+                Some(expected) => verify_secret(password, expected).await,
+                None => false,
+            },
+            None => false,
+        };
+    }
+
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return match &web_auth.bearer_token {
+            Some(expected) => verify_secret(credentials.trim(), expected).await,
+            None => false,
+        };
+    }
+
+    false
+}
+
+/// Compare a secret with its bcrypt hash, or in constant time with its plain text value.
+async fn verify_secret(secret: &str, expected: &str) -> bool {
+    if is_bcrypt_hash(expected) {
+        // bcrypt is deliberately slow, hash on a blocking thread to keep serving other requests.
+        let (secret, expected) = (secret.to_string(), expected.to_string());
+        return tokio::task::spawn_blocking(move || {
+            bcrypt::verify(secret, &expected).unwrap_or(false)
+        })
+        .await
+        .unwrap_or(false);
+    }
+
+    secret.len() == expected.len() && memcmp::eq(secret.as_bytes(), expected.as_bytes())
+}
+
+fn unauthorized(web_auth: &WebAuth) -> Response<Body> {
+    // Only advertise bearer authentication when no basic auth user is configured.
+    let challenge = if web_auth.basic_auth_users.is_empty() {
+        "Bearer realm=\"tado-exporter\""
+    } else {
+        "Basic realm=\"tado-exporter\""
+    };
+
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(WWW_AUTHENTICATE, challenge)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from("unauthorized"))
+        .unwrap()
+}
+
+fn tls_acceptor(tls: &TlsConfig) -> Result<SslAcceptor, ErrorStack> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    builder.set_certificate_chain_file(&tls.cert_file)?;
+    builder.set_private_key_file(&tls.key_file, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+
+    Ok(builder.build())
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+async fn bind(address: &ListenAddress) -> io::Result<Listener> {
+    match address {
+        ListenAddress::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr).await?)),
+        ListenAddress::Unix(path) => {
+            remove_stale_socket(path)?;
+            Ok(Listener::Unix(UnixListener::bind(path)?))
+        }
+    }
+}
+
+/// Remove a socket left behind by a previous run, which would make the bind fail.
+///
+/// Anything else at the path, or a socket a server still listens on, is left untouched.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match StdUnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("a server already listens on {}", path.display()),
+        )),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(e) => Err(e),
+    }
+}
+
+impl Listener {
+    async fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Box::new(stream))
+            }
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+
+    async fn run(self, acceptor: Option<SslAcceptor>, sender: mpsc::Sender<Box<dyn Connection>>) {
+        loop {
+            let connection = match self.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    // Errors such as too many open files persist, retrying at once would spin.
+                    error!("unable to accept connection: {e}");
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+
+            let sender = sender.clone();
+            match &acceptor {
+                // Run the handshake separately so a slow client does not block the listener.
+                Some(acceptor) => {
+                    let acceptor = acceptor.clone();
+                    tokio::spawn(async move {
+                        match tls_handshake(&acceptor, connection).await {
+                            Ok(stream) => {
+                                let _ = sender.send(Box::new(stream)).await;
+                            }
+                            Err(e) => warn!("TLS handshake failed: {e}"),
+                        }
+                    });
+                }
+                None => {
+                    let _ = sender.send(connection).await;
+                }
+            }
+        }
+    }
+}
+
+async fn tls_handshake(
+    acceptor: &SslAcceptor,
+    connection: Box<dyn Connection>,
+) -> Result<SslStream<Box<dyn Connection>>, Box<dyn std::error::Error + Send + Sync>> {
+    let ssl = Ssl::new(acceptor.context())?;
+    let mut stream = SslStream::new(ssl, connection)?;
+    Pin::new(&mut stream).accept().await?;
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use std::collections::HashMap;
+
+    fn web_auth() -> WebAuth {
+        WebAuth {
+            basic_auth_users: HashMap::from([
+                ("hashed".to_string(), bcrypt::hash("password", 4).unwrap()),
+                ("plain".to_string(), "password".to_string()),
+            ]),
+            bearer_token: Some("token".to_string()),
+        }
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", BASE64.encode(credentials))
+    }
+
+    #[rstest]
+    #[case(Some(basic("hashed:password")), true)]
+    #[case(Some(basic("plain:password")), true)]
+    #[case(Some(basic("hashed:wrong")), false)]
+    #[case(Some(basic("plain:passwor")), false)]
+    #[case(Some(basic("unknown:password")), false)]
+    #[case(Some("Basic not-base64".to_string()), false)]
+    #[case(Some("Bearer token".to_string()), true)]
+    #[case(Some("bearer token".to_string()), true)]
+    #[case(Some("Bearer wrong".to_string()), false)]
+    #[case(Some("Digest token".to_string()), false)]
+    #[case(None, false)]
+    #[actix_rt::test]
+    async fn test_is_authorized(#[case] authorization: Option<String>, #[case] expected: bool) {
+        /*
+        GIVEN hashed and plain text basic auth users and a bearer token
+        WHEN a request presents an Authorization header
+        THEN it is authorized only with valid credentials
+        */
+
+        // GIVEN
+        let web_auth = web_auth();
+
+        // WHEN
+        let actual = is_authorized(&web_auth, authorization.as_deref()).await;
+
+        // THEN
+        assert_eq!(actual, expected);
+    }
+
+    fn socket_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("tado-exporter-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+
+        path
+    }
+
+    #[test]
+    fn test_remove_stale_socket() {
+        /*
+        GIVEN a socket left behind by a previous run, a socket in use and a regular file
+        WHEN they are removed before binding
+        THEN only the stale socket is removed
+        */
+
+        // GIVEN
+        let stale = socket_path("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+        let in_use = socket_path("in_use.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&in_use).unwrap();
+        let file = socket_path("file.sock");
+        fs::write(&file, "content").unwrap();
+
+        // WHEN
+        let stale_result = remove_stale_socket(&stale);
+        let in_use_result = remove_stale_socket(&in_use);
+        let file_result = remove_stale_socket(&file);
+
+        // THEN
+        assert!(stale_result.is_ok());
+        assert!(!stale.exists());
+        assert_eq!(in_use_result.unwrap_err().kind(), ErrorKind::AddrInUse);
+        assert!(in_use.exists());
+        assert_eq!(file_result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "content");
+        assert!(remove_stale_socket(&socket_path("missing.sock")).is_ok());
+    }
+
+    #[rstest]
+    #[case(web_auth(), "Basic realm=\"tado-exporter\"")]
+    #[case(
+        WebAuth { basic_auth_users: HashMap::new(), bearer_token: Some("token".to_string()) },
+        "Bearer realm=\"tado-exporter\""
+    )]
+    fn test_unauthorized(#[case] web_auth: WebAuth, #[case] expected: &str) {
+        /*
+        GIVEN basic auth users or only a bearer token
+        WHEN a request is refused
+        THEN the configured authentication scheme is advertised
+        */
+
+        // WHEN
+        let actual = unauthorized(&web_auth);
+
+        // THEN
+        assert_eq!(actual.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(actual.headers()[WWW_AUTHENTICATE], expected);
+    }
+
+    #[actix_rt::test]
+    async fn test_route_requires_credentials() {
+        /*
+        GIVEN authentication is enabled
+        WHEN endpoints are requested without credentials
+        THEN metrics are refused while the probes stay reachable
+        */
+
+        // GIVEN
+        let web_auth = Arc::new(web_auth());
+        let request = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+
+        // WHEN
+        let metrics = route(request("/metrics"), web_auth.clone()).await.unwrap();
+        let healthz = route(request("/healthz"), web_auth.clone()).await.unwrap();
+
+        // THEN
+        assert_eq!(metrics.status(), StatusCode::UNAUTHORIZED);
+        assert!(metrics.headers().contains_key(WWW_AUTHENTICATE));
+        assert_eq!(healthz.status(), StatusCode::OK);
+    }
+}
@@ -7,6 +7,7 @@ pub struct AuthStartResponse {
     pub device_code: String,
     pub expires_in: u64,
     pub interval: u64,
+    pub user_code: String,
     pub verification_uri_complete: String,
 }
 
@@ -30,6 +31,7 @@ pub struct MeApiResponse {
 #[derive(Deserialize, Debug)]
 pub struct HomesApiResponse {
     pub id: i32,
+    pub name: String,
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -39,8 +41,10 @@ pub struct ZonesApiResponse {
     pub name: String,
     pub setting: ZoneStateSettingApiResponse,
     pub heatingPower: Option<ActivityDataPointsHeatingPowerApiResponse>,
+    pub acPower: Option<ActivityDataPointsAcPowerApiResponse>,
     pub sensorDataPoints: ZoneStateSensorDataPointsApiResponse,
     pub openWindow: Option<ZoneStateOpenWindowApiResponse>,
+    pub manualControlTermination: Option<ZoneOverlayTerminationApiResponse>,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -55,8 +59,15 @@ pub struct ZoneStateOpenWindowApiResponse {
 #[derive(Deserialize, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct ZoneStateSettingApiResponse {
+    pub r#type: Option<String>,
     pub power: String,
     pub temperature: Option<ZoneStateSettingTemperatureApiResponse>,
+    // Air conditioning settings
+    pub mode: Option<String>,
+    #[serde(alias = "fanLevel")]
+    pub fanSpeed: Option<String>,
+    #[serde(alias = "verticalSwing")]
+    pub swing: Option<String>,
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -69,6 +80,11 @@ pub struct ActivityDataPointsHeatingPowerApiResponse {
     pub percentage: f64,
 }
 
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ActivityDataPointsAcPowerApiResponse {
+    pub value: String,
+}
+
 #[derive(Deserialize, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct ZoneStateSensorDataPointsApiResponse {
@@ -79,11 +95,81 @@ pub struct ZoneStateSensorDataPointsApiResponse {
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct SensorDataPointsInsideTemperatureApiResponse {
     pub value: f64,
+    pub timestamp: Option<String>, // RFC 3339 timestamp
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct SensorDataPointsHumidityApiResponse {
     pub percentage: f64,
+    pub timestamp: Option<String>, // RFC 3339 timestamp
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ZoneListApiResponse {
+    pub id: i32,
+    pub name: String,
+    pub r#type: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct HotWaterZoneStateApiResponse {
+    pub setting: HotWaterSettingApiResponse,
+    pub overlay: Option<ZoneOverlayApiResponse>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct HotWaterSettingApiResponse {
+    pub power: String,
+    pub temperature: Option<HotWaterSettingTemperatureApiResponse>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct HotWaterSettingTemperatureApiResponse {
+    pub celsius: f64,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ZoneOverlayApiResponse {
+    pub r#type: String,
+    pub termination: Option<ZoneOverlayTerminationApiResponse>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct ZoneOverlayTerminationApiResponse {
+    pub r#type: String,
+    pub remainingTimeInSeconds: Option<i32>,
+    pub projectedExpiry: Option<String>, // RFC 3339 timestamp
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct RoomsAndDevicesApiResponse {
+    pub rooms: Vec<RoomDevicesApiResponse>,
+    #[serde(default)]
+    pub otherDevices: Vec<DeviceApiResponse>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct RoomDevicesApiResponse {
+    pub roomName: String,
+    pub devices: Vec<DeviceApiResponse>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct DeviceApiResponse {
+    pub serialNumber: String,
+    pub r#type: String,
+    pub firmwareVersion: Option<String>,
+    pub batteryState: Option<String>,
+    pub connection: Option<DeviceConnectionApiResponse>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct DeviceConnectionApiResponse {
+    pub state: String,
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -91,12 +177,18 @@ pub struct SensorDataPointsHumidityApiResponse {
 pub struct WeatherApiResponse {
     pub solarIntensity: WeatherSolarIntensityApiResponse,
     pub outsideTemperature: WeatherOutsideTemperatureApiResponse,
+    pub weatherState: Option<WeatherStateApiResponse>,
 }
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct WeatherSolarIntensityApiResponse {
     pub percentage: f64,
 }
 
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct WeatherStateApiResponse {
+    pub value: String,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct WeatherOutsideTemperatureApiResponse {
     pub fahrenheit: f64,
@@ -1,28 +1,29 @@
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::Error;
-use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
 
 use lazy_static::lazy_static;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use reqwest;
-use std::fs;
 
-use super::error::AuthError;
-use super::model::{Weather, ZoneState};
+use super::error::{AuthError, HomeError};
+use super::metrics;
+use super::model::{Device, Home, HomeFilter, Retrieved, Weather, ZoneState};
+use super::token_store::{StoredTokens, TokenStore};
 
 use super::api::{
-    AuthStartResponse, AuthTokensErrorResponse, AuthTokensResponse, MeApiResponse,
-    WeatherApiResponse, ZonesApiResponse,
+    AuthStartResponse, AuthTokensErrorResponse, AuthTokensResponse, HotWaterZoneStateApiResponse,
+    MeApiResponse, RoomsAndDevicesApiResponse, WeatherApiResponse, ZoneListApiResponse,
+    ZonesApiResponse,
 };
 
 const AUTH_PENDING_MESSAGE: &str = "authorization_pending";
+const AUTH_INVALID_GRANT_MESSAGE: &str = "invalid_grant";
 
 lazy_static! {
     // TODO: POST DEVICE - https://login.tado.com/oauth2/device
-    static ref AUTH_START_URL: reqwest::Url = "https://login.tado.com/oauth2/device_authorize".parse().unwrap();
-    static ref AUTH_TOKEN_URL: reqwest::Url = "https://login.tado.com/oauth2/token".parse().unwrap();
+    pub static ref AUTH_URL: reqwest::Url = "https://login.tado.com/oauth2/".parse().unwrap();
     pub static ref BASE_URL: reqwest::Url = "https://my.tado.com/api/v2/".parse().unwrap();
     pub static ref HOPS_URL: reqwest::Url = "https://hops.tado.com/".parse().unwrap();
 }
@@ -31,38 +32,71 @@ pub struct Client {
     http_client: reqwest::Client,
     base_url: reqwest::Url,
     hops_url: reqwest::Url,
+    auth_url: reqwest::Url,
 
     // API Authentication information.
     client_id: String,
-    token_file: String,
+    token_store: Box<dyn TokenStore>,
     access_token: String,
     refresh_token: String,
     tokens_refresh_by: Instant,
-
-    home_id: i32,
+    tokens_expire_at: SystemTime,
+    // Whether the current tokens are stored, storing them is retried until they are.
+    tokens_saved: bool,
+    // Homes recorded with the stored tokens.
+    stored_home_ids: Vec<i32>,
+
+    home_filter: HomeFilter,
+    // Selected homes, `None` until they are retrieved.
+    homes: Option<Vec<Home>>,
+    // Hot water zones of each home, retrieved once as they are not part of the home rooms.
+    hot_water_zones: HashMap<i32, Vec<ZoneListApiResponse>>,
+
+    // Endpoints which failed since the errors were last taken.
+    scrape_errors: Vec<&'static str>,
 }
 
 impl Client {
-    pub fn new(client_id: String, token_file: String) -> Client {
-        Client::with_base_url(BASE_URL.clone(), HOPS_URL.clone(), client_id, token_file)
+    pub fn new(
+        client_id: String,
+        token_store: Box<dyn TokenStore>,
+        home_filter: HomeFilter,
+    ) -> Client {
+        Client::with_base_url(
+            BASE_URL.clone(),
+            HOPS_URL.clone(),
+            AUTH_URL.clone(),
+            client_id,
+            token_store,
+            home_filter,
+        )
     }
 
     fn with_base_url(
         base_url: reqwest::Url,
         hops_url: reqwest::Url,
+        auth_url: reqwest::Url,
         client_id: String,
-        token_file: String,
+        token_store: Box<dyn TokenStore>,
+        home_filter: HomeFilter,
     ) -> Client {
         Client {
             http_client: reqwest::Client::new(),
             base_url,
             hops_url,
+            auth_url,
             client_id,
-            token_file,
+            token_store,
             access_token: String::default(),
             refresh_token: String::default(),
             tokens_refresh_by: Instant::now(),
-            home_id: 0,
+            tokens_expire_at: UNIX_EPOCH,
+            tokens_saved: true,
+            stored_home_ids: Vec::new(),
+            home_filter,
+            homes: None,
+            hot_water_zones: HashMap::new(),
+            scrape_errors: Vec::new(),
         }
     }
 
@@ -74,14 +108,61 @@ impl Client {
     /// To avoid manual intervention, the method also attempts to complete the login challenge
     /// on behalf of the user.
     pub async fn authenticate(&mut self) -> Result<(), AuthError> {
-        self.load_tokens().expect("Loading tokens should not fail");
+        if let Err(e) = self.load_tokens() {
+            warn!("unable to load the stored refresh token: {e}");
+        }
 
-        if let Ok(()) = self.refresh_authentication().await {
-            info!("Refreshed authentication tokens");
+        match self.refresh_authentication().await {
+            Ok(()) => {
+                info!("Refreshed authentication tokens");
 
-            return Ok(());
+                return Ok(());
+            }
+            // The stored token was refused or is unusable, try the backup.
+            Err(AuthError::InvalidGrant) | Err(AuthError::MissingRefreshToken) => {}
+            // Keep the stored token on other errors, e.g. an outage of the token endpoint:
+            // the backup was superseded by it and must not be sent again.
+            Err(e) => return Err(e),
+        }
+
+        // The token file may hold a token which was never accepted, try the previous one.
+        if let Ok(Some(backup)) = self.token_store.load_backup() {
+            match StoredTokens::parse(&backup) {
+                Ok(backup)
+                    if backup.refresh_token != self.refresh_token
+                        && self.issued_to_client(&backup) =>
+                {
+                    self.refresh_token = backup.refresh_token;
+
+                    match self.refresh_authentication().await {
+                        Ok(()) => {
+                            info!("Refreshed authentication tokens using the backup token");
+
+                            return Ok(());
+                        }
+                        Err(AuthError::InvalidGrant) | Err(AuthError::MissingRefreshToken) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("unable to parse the backup token: {e}"),
+            }
+        }
+
+        // Only a refused or missing token requires a new login.
+        let result = self.device_authentication().await;
+        if let Err(e) = &result {
+            if !matches!(e, AuthError::Storage(_)) {
+                metrics::set_unauthenticated();
+            }
         }
 
+        result
+    }
+
+    /// Authenticate using the device authentication flow, which requires the user to
+    /// visit the verification URL.
+    async fn device_authentication(&mut self) -> Result<(), AuthError> {
         // Start device authentication flow.
         let start_params = [
             ("client_id", self.client_id.as_str()),
@@ -89,7 +170,7 @@ impl Client {
         ];
         let resp = self
             .http_client
-            .post(AUTH_START_URL.clone())
+            .post(self.auth_url.join("device_authorize").unwrap())
             .form(&start_params)
             .send()
             .await?;
@@ -99,10 +180,10 @@ impl Client {
             "Started device authentication flow with URL {}",
             start.verification_uri_complete
         );
+        metrics::set_authentication_pending(&start.verification_uri_complete, &start.user_code);
 
         // Wait for API tokens to be returned once the flow is complete.
-        self.wait_for_tokens(start).await?;
-        Ok(())
+        self.wait_for_tokens(start).await
     }
 
     async fn get(&self, url: reqwest::Url) -> Result<reqwest::Response, reqwest::Error> {
@@ -123,8 +204,8 @@ impl Client {
         Ok(me_api_response)
     }
 
-    async fn zones(&mut self) -> Result<Vec<ZonesApiResponse>, reqwest::Error> {
-        let endpoint = format!("homes/{}/rooms", self.home_id);
+    async fn zones(&self, home_id: i32) -> Result<Vec<ZonesApiResponse>, reqwest::Error> {
+        let endpoint = format!("homes/{home_id}/rooms");
         let url = self.hops_url.join(&endpoint).unwrap();
 
         let resp = self.get(url).await?;
@@ -135,8 +216,48 @@ impl Client {
         Ok(zones_api_reponse)
     }
 
-    async fn weather(&self) -> Result<WeatherApiResponse, reqwest::Error> {
-        let endpoint = format!("homes/{}/weather/", self.home_id);
+    async fn zone_list(&self, home_id: i32) -> Result<Vec<ZoneListApiResponse>, reqwest::Error> {
+        let endpoint = format!("homes/{home_id}/zones");
+        let url = self.base_url.join(&endpoint).unwrap();
+
+        let resp = self.get(url).await?;
+
+        let zone_list_api_response = resp.json::<Vec<ZoneListApiResponse>>().await?;
+        debug!("{:#?}", zone_list_api_response);
+
+        Ok(zone_list_api_response)
+    }
+
+    async fn hot_water_state(
+        &self,
+        home_id: i32,
+        zone_id: i32,
+    ) -> Result<HotWaterZoneStateApiResponse, reqwest::Error> {
+        let endpoint = format!("homes/{home_id}/zones/{zone_id}/state");
+        let url = self.base_url.join(&endpoint).unwrap();
+
+        let resp = self.get(url).await?;
+
+        let hot_water_api_response = resp.json::<HotWaterZoneStateApiResponse>().await?;
+        debug!("{:#?}", hot_water_api_response);
+
+        Ok(hot_water_api_response)
+    }
+
+    async fn devices(&self, home_id: i32) -> Result<RoomsAndDevicesApiResponse, reqwest::Error> {
+        let endpoint = format!("homes/{home_id}/roomsAndDevices");
+        let url = self.hops_url.join(&endpoint).unwrap();
+
+        let resp = self.get(url).await?;
+
+        let devices_api_response = resp.json::<RoomsAndDevicesApiResponse>().await?;
+        debug!("{:#?}", devices_api_response);
+
+        Ok(devices_api_response)
+    }
+
+    async fn weather(&self, home_id: i32) -> Result<WeatherApiResponse, reqwest::Error> {
+        let endpoint = format!("homes/{home_id}/weather/");
         let url = self.base_url.join(&endpoint).unwrap();
 
         let resp = self.get(url).await?;
@@ -149,11 +270,34 @@ impl Client {
     }
 
     /// Refresh the API access token if it expired.
+    ///
+    /// Tokens which could not be stored when they were issued are stored again.
     pub async fn refresh_authentication(&mut self) -> Result<(), AuthError> {
         if Instant::now() < self.tokens_refresh_by {
+            if !self.tokens_saved {
+                self.save_tokens().map_err(AuthError::Storage)?;
+            }
+
             return Ok(());
         }
 
+        // The access token expired, so we are no longer authenticated until the refresh succeeds.
+        let result = self.refresh_tokens().await;
+        if let Err(e) = &result {
+            if !matches!(e, AuthError::Storage(_)) {
+                metrics::set_unauthenticated();
+            }
+        }
+
+        result
+    }
+
+    async fn refresh_tokens(&mut self) -> Result<(), AuthError> {
+        // The token endpoint would reject the request as invalid, not as an invalid grant.
+        if self.refresh_token.is_empty() {
+            return Err(AuthError::MissingRefreshToken);
+        }
+
         let refresh_params = [
             ("client_id", self.client_id.as_str()),
             ("grant_type", "refresh_token"),
@@ -162,107 +306,306 @@ impl Client {
         debug!("Requesting refesh token");
         let resp = self
             .http_client
-            .post(AUTH_TOKEN_URL.clone())
+            .post(self.auth_url.join("token").unwrap())
             .form(&refresh_params)
             .send()
             .await?;
 
-        let tokens = resp.json::<AuthTokensResponse>().await?;
+        let result = refresh_tokens_response(resp).await;
+        if let Err(AuthError::InvalidGrant) = result {
+            metrics::set_refresh_token_rejected();
+        }
+        let tokens = result?;
         debug!("{:#?}", tokens);
 
         self.set_tokens(tokens)
-            .expect("Unable to write auth tokens");
+    }
+
+    /// Start over with the device authentication flow, after the refresh token was rejected
+    /// or when there is none.
+    pub async fn reauthenticate(&mut self) -> Result<(), AuthError> {
+        self.access_token = String::default();
+        self.refresh_token = String::default();
+
+        let result = self.device_authentication().await;
+        if let Err(e) = &result {
+            if !matches!(e, AuthError::Storage(_)) {
+                metrics::set_unauthenticated();
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve the homes to scrape (only if we don't already know them).
+    ///
+    /// Homes are selected using the configured home filter and ordered by identifier.
+    /// Every explicitly included home must be linked to the account.
+    pub async fn retrieve_homes(&mut self) -> Result<(), HomeError> {
+        if self.homes.is_some() {
+            return Ok(());
+        }
+
+        let me_response = self.me().await?;
+
+        let mut homes: Vec<Home> = me_response.homes.iter().map(|h| h.convert()).collect();
+        homes.sort_by_key(|h| h.id);
+
+        for selector in &self.home_filter.include {
+            if !homes.iter().any(|h| h.matches(selector)) {
+                return Err(HomeError::NotFound(selector.clone()));
+            }
+        }
+
+        homes.retain(|h| self.home_filter.allows(h));
+        if homes.is_empty() {
+            warn!("no home selected, check the configured home filters");
+        }
+
+        for home in &homes {
+            info!("selected home {} ({})", home.name, home.id);
+        }
+
+        let home_ids: Vec<i32> = homes.iter().map(|h| h.id).collect();
+        // An empty selection is kept as well, so that it is not retrieved again on every tick.
+        self.homes = Some(homes);
+
+        if home_ids != self.stored_home_ids {
+            // Dashboards and alerts of the previous homes no longer get values.
+            if !self.stored_home_ids.is_empty() {
+                warn!(
+                    "selected homes changed from {:?} to {:?} since the last run",
+                    self.stored_home_ids, home_ids
+                );
+            }
+
+            // Record the selected homes alongside the tokens.
+            if !self.refresh_token.is_empty() {
+                match self.save_tokens() {
+                    Ok(()) => self.stored_home_ids = home_ids,
+                    Err(e) => warn!("unable to save tokens: {e}"),
+                }
+            }
+        }
 
         Ok(())
     }
 
-    pub async fn retrieve_zones(&mut self) -> Vec<ZoneState> {
-        // retrieve home details (only if we don't already have a home identifier)
-        if self.home_id == 0 {
-            let me_response = match self.me().await {
+    /// Retrieve the zones of the selected homes, see `retrieve_homes`.
+    pub async fn retrieve_zones(&mut self) -> Retrieved<ZoneState> {
+        let mut response = Retrieved::<ZoneState>::default();
+
+        for home in self.homes.iter().flatten() {
+            // retrieve home different zones
+            let zones_response = match self.zones(home.id).await {
                 Ok(resp) => resp,
                 Err(e) => {
-                    error!("unable to retrieve home identifier: {e}");
-                    return Vec::new();
+                    error!("unable to retrieve zones for home {}: {e}", home.name);
+                    self.scrape_errors.push("zones");
+                    response.failed_homes.push(home.id);
+                    continue;
                 }
             };
 
-            self.home_id = me_response.homes.first().unwrap().id;
-        }
+            for zone in zones_response {
+                info!(
+                    "retrieving zone details for {} ({})...",
+                    zone.name, home.name
+                );
 
-        // retrieve home different zones
-        let zones_response = match self.zones().await {
-            Ok(resp) => resp,
-            Err(e) => {
-                error!("unable to retrieve home zones: {e}");
-                return Vec::new();
+                response.values.push(zone.convert(home));
             }
-        };
 
-        let mut response = Vec::<ZoneState>::new();
+            // retrieve hot water zones, which are not part of the home rooms
+            if !self.hot_water_zones.contains_key(&home.id) {
+                let zone_list_response = match self.zone_list(home.id).await {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        error!("unable to retrieve zone list for home {}: {e}", home.name);
+                        self.scrape_errors.push("zone_list");
+                        response.failed_homes.push(home.id);
+                        continue;
+                    }
+                };
+
+                let hot_water_zones = zone_list_response
+                    .into_iter()
+                    .filter(|zone| zone.r#type == "HOT_WATER")
+                    .collect();
+                self.hot_water_zones.insert(home.id, hot_water_zones);
+            }
+
+            for zone in &self.hot_water_zones[&home.id] {
+                info!(
+                    "retrieving hot water details for {} ({})...",
+                    zone.name, home.name
+                );
+
+                match self.hot_water_state(home.id, zone.id).await {
+                    Ok(state) => response.values.push(state.convert(home, &zone.name)),
+                    Err(e) => {
+                        error!("unable to retrieve hot water state for {}: {e}", zone.name);
+                        self.scrape_errors.push("hot_water");
+                        if !response.failed_homes.contains(&home.id) {
+                            response.failed_homes.push(home.id);
+                        }
+                    }
+                }
+            }
+        }
+
+        response
+    }
+
+    /// Retrieve the devices of the selected homes, see `retrieve_homes`.
+    pub async fn retrieve_devices(&mut self) -> Retrieved<Device> {
+        info!("retrieving devices details ...");
+
+        let mut response = Retrieved::<Device>::default();
 
-        for zone in zones_response {
-            info!("retrieving zone details for {}...", zone.name);
+        for home in self.homes.iter().flatten() {
+            // retrieve home devices, grouped by zone
+            let devices_response = match self.devices(home.id).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    error!("unable to retrieve devices for home {}: {e}", home.name);
+                    self.scrape_errors.push("devices");
+                    response.failed_homes.push(home.id);
+                    continue;
+                }
+            };
 
-            response.push(zone.convert());
+            response.values.extend(devices_response.convert(home));
         }
 
         response
     }
 
-    pub async fn retrieve_weather(&mut self) -> Option<Weather> {
+    /// Retrieve the weather of the selected homes, see `retrieve_homes`.
+    pub async fn retrieve_weather(&mut self) -> Retrieved<Weather> {
         info!("retrieving weather details ...");
 
-        // retrieve home details (only if we don't already have a home identifier)
-        if self.home_id == 0 {
-            let me_response = match self.me().await {
+        let mut response = Retrieved::<Weather>::default();
+
+        for home in self.homes.iter().flatten() {
+            // retrieve weather state
+            let weather_response = match self.weather(home.id).await {
                 Ok(resp) => resp,
                 Err(e) => {
-                    error!("unable to retrieve home identifier: {e}");
-                    return None;
+                    error!(
+                        "unable to retrieve weather info for home {}: {e}",
+                        home.name
+                    );
+                    self.scrape_errors.push("weather");
+                    response.failed_homes.push(home.id);
+                    continue;
                 }
             };
 
-            self.home_id = me_response.homes.first().unwrap().id;
+            response.values.push(weather_response.convert(home));
         }
 
-        // retrieve weather state
-        let weather_response = match self.weather().await {
-            Ok(resp) => resp,
-            Err(e) => {
-                error!("unable to retrieve weather info: {e}");
-                return None;
-            }
-        };
+        response
+    }
 
-        Some(weather_response.convert())
+    /// Take the endpoints which failed since the last call, one entry per failure.
+    pub fn take_scrape_errors(&mut self) -> Vec<&'static str> {
+        std::mem::take(&mut self.scrape_errors)
     }
 
     /// Set the API access tokens to use and manage related metadata.
-    fn set_tokens(&mut self, tokens: AuthTokensResponse) -> Result<(), Error> {
+    ///
+    /// The tokens are used even if storing them fails, storing them is then retried
+    /// on the next refresh.
+    fn set_tokens(&mut self, tokens: AuthTokensResponse) -> Result<(), AuthError> {
         // Reduce the tokens validity slightly to refresh before they expire.
         let expires_in = tokens.expires_in - 10;
 
-        File::create(&self.token_file)?.write_all(tokens.refresh_token.as_bytes())?;
-
         self.access_token = tokens.access_token;
         self.refresh_token = tokens.refresh_token;
         self.tokens_refresh_by = Instant::now() + Duration::from_secs(expires_in);
+        self.tokens_expire_at = SystemTime::now() + Duration::from_secs(tokens.expires_in);
 
-        Ok(())
+        metrics::set_authenticated(self.tokens_expire_at);
+
+        self.save_tokens().map_err(AuthError::Storage)
+    }
+
+    fn save_tokens(&mut self) -> Result<(), Error> {
+        let tokens = StoredTokens {
+            refresh_token: self.refresh_token.clone(),
+            access_token: Some(self.access_token.clone()),
+            expires_at: self
+                .tokens_expire_at
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+            client_id: Some(self.client_id.clone()),
+            // Keep the recorded homes until the selected ones are retrieved.
+            home_ids: match &self.homes {
+                Some(homes) => homes.iter().map(|home| home.id).collect(),
+                None => self.stored_home_ids.clone(),
+            },
+        };
+
+        let result = self.token_store.save(&tokens.to_json());
+        self.tokens_saved = result.is_ok();
+
+        result
     }
 
     fn load_tokens(&mut self) -> Result<(), Error> {
-        if let Ok(json) = fs::read_to_string(&self.token_file) {
-            // Ignore if file is not there
-            self.refresh_token = json;
+        // Ignore if file is not there
+        let tokens = match self.token_store.load()? {
+            Some(content) => {
+                StoredTokens::parse(&content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+            }
+            None => return Ok(()),
+        };
 
-            debug!("Loaded refresh token")
+        // Tokens are bound to the client they were issued to, refreshing them would fail.
+        if !self.issued_to_client(&tokens) {
+            error!(
+                "Stored tokens were issued to client id {}, not to the configured client id {}, a new login is required",
+                tokens.client_id.unwrap_or_default(),
+                self.client_id
+            );
+
+            return Ok(());
+        }
+
+        self.refresh_token = tokens.refresh_token;
+        self.stored_home_ids = tokens.home_ids;
+        debug!("Loaded refresh token");
+
+        // Reuse the access token while it is valid, so a restart doesn't require a refresh.
+        if let (Some(access_token), Some(expires_at)) = (tokens.access_token, tokens.expires_at) {
+            let expires_at = UNIX_EPOCH + Duration::from_secs(expires_at);
+
+            if let Ok(remaining) = expires_at.duration_since(SystemTime::now()) {
+                if remaining > Duration::from_secs(10) {
+                    self.access_token = access_token;
+                    self.tokens_refresh_by = Instant::now() + remaining - Duration::from_secs(10);
+                    self.tokens_expire_at = expires_at;
+                    metrics::set_authenticated(expires_at);
+
+                    debug!("Loaded access token valid for {}s", remaining.as_secs());
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Whether stored tokens were issued to the configured client, tokens written by
+    /// older versions don't record the client id and are assumed to be.
+    fn issued_to_client(&self, tokens: &StoredTokens) -> bool {
+        match &tokens.client_id {
+            Some(client_id) => *client_id == self.client_id,
+            None => true,
+        }
+    }
+
     async fn wait_for_tokens(&mut self, start: AuthStartResponse) -> Result<(), AuthError> {
         let must_complete_by = Instant::now() + Duration::from_secs(start.expires_in);
         let token_params = [
@@ -273,7 +616,7 @@ impl Client {
         while Instant::now() < must_complete_by {
             let resp = self
                 .http_client
-                .post(AUTH_TOKEN_URL.clone())
+                .post(self.auth_url.join("token").unwrap())
                 .form(&token_params)
                 .send()
                 .await?;
@@ -281,10 +624,8 @@ impl Client {
                 reqwest::StatusCode::OK => {
                     let tokens = resp.json::<AuthTokensResponse>().await?;
                     debug!("{:#?}", tokens);
-                    self.set_tokens(tokens)
-                        .expect("Unable to write auth tokens");
                     info!("Device authentication flow completed");
-                    return Ok(());
+                    return self.set_tokens(tokens);
                 }
                 reqwest::StatusCode::BAD_REQUEST => {
                     let error = resp
@@ -312,34 +653,409 @@ impl Client {
     }
 }
 
-#[cfg(test)]
+/// Read the tokens from a refresh request response, detecting a rejected refresh token.
+async fn refresh_tokens_response(resp: reqwest::Response) -> Result<AuthTokensResponse, AuthError> {
+    let status = resp.status();
+    let url = resp.url().clone();
+
+    match status {
+        reqwest::StatusCode::OK => Ok(resp.json::<AuthTokensResponse>().await?),
+        reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::UNAUTHORIZED => {
+            let failure = resp.json::<AuthTokensErrorResponse>().await?;
+            if failure.error == AUTH_INVALID_GRANT_MESSAGE {
+                return Err(AuthError::InvalidGrant);
+            }
+
+            Err(AuthError::UnexpectedStatus(status, url))
+        }
+        _ => Err(AuthError::UnexpectedStatus(status, url)),
+    }
+}
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
     use crate::tado::api::{
-        WeatherOutsideTemperatureApiResponse, WeatherSolarIntensityApiResponse,
+        ActivityDataPointsAcPowerApiResponse, WeatherOutsideTemperatureApiResponse,
+        WeatherSolarIntensityApiResponse, WeatherStateApiResponse,
+        ZoneOverlayTerminationApiResponse,
     };
 
+    use crate::tado::model::ZoneType;
+    use crate::tado::status;
+    use crate::tado::token_file::{self, tests::token_path};
+    use crate::tado::token_store::{FileTokenStore, MemoryTokenStore};
+
     use rstest::*;
-    use wiremock::matchers::{method, path};
+    use wiremock::matchers::{body_string_contains, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn test_new() {
-        let client = Client::new("client_id".to_string(), ".token_file".to_string());
+        let client = Client::new(
+            "client_id".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
 
         assert_eq!(client.client_id, "client_id");
         assert_eq!(client.base_url, *BASE_URL);
     }
 
+    #[test]
+    fn test_load_tokens_with_valid_access_token() {
+        let _serial = metrics::tests::serial();
+        /*
+        GIVEN stored tokens with an access token which is still valid
+        WHEN the tokens are loaded
+        THEN the stored access token is used until it expires
+        */
+
+        // GIVEN
+        let expires_at = SystemTime::now() + Duration::from_secs(600);
+        let mut token_store = MemoryTokenStore::default();
+        token_store
+            .save(
+                &StoredTokens {
+                    refresh_token: "refresh".to_string(),
+                    access_token: Some("access".to_string()),
+                    expires_at: Some(expires_at.duration_since(UNIX_EPOCH).unwrap().as_secs()),
+                    client_id: Some("client_id".to_string()),
+                    home_ids: vec![123],
+                }
+                .to_json(),
+            )
+            .unwrap();
+
+        let mut client = Client::new(
+            "client_id".to_string(),
+            Box::new(token_store),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        client.load_tokens().unwrap();
+
+        // THEN
+        assert_eq!(client.access_token, "access");
+        assert_eq!(client.refresh_token, "refresh");
+        assert!(client.tokens_refresh_by > Instant::now() + Duration::from_secs(500));
+    }
+
+    #[rstest(
+        content,
+        expected_refresh_token,
+        case(r#"{"refresh_token":"refresh","client_id":"other"}"#, ""),
+        case(r#"{"refresh_token":"refresh","client_id":"client_id"}"#, "refresh"),
+        case("refresh", "refresh")
+    )]
+    fn test_load_tokens(content: &str, expected_refresh_token: &str) {
+        /*
+        GIVEN stored tokens, possibly issued to another client or in the plain text format
+        WHEN the tokens are loaded
+        THEN only tokens of the configured client are used
+        */
+
+        // GIVEN
+        let mut token_store = MemoryTokenStore::default();
+        token_store.save(content).unwrap();
+
+        let mut client = Client::new(
+            "client_id".to_string(),
+            Box::new(token_store),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        client.load_tokens().unwrap();
+
+        // THEN
+        assert_eq!(client.refresh_token, expected_refresh_token);
+        assert_eq!(client.access_token, "");
+    }
+
+    #[rstest(
+        status,
+        body,
+        expected,
+        case(400, r#"{"error":"invalid_grant"}"#, "InvalidGrant"),
+        case(400, r#"{"error":"invalid_request"}"#, "UnexpectedStatus"),
+        case(500, "", "UnexpectedStatus"),
+        case(
+            200,
+            r#"{"access_token":"access","expires_in":600,"refresh_token":"refresh"}"#,
+            "Ok"
+        )
+    )]
+    #[actix_rt::test]
+    async fn test_refresh_tokens_response(status: u16, body: &str, expected: &str) {
+        /*
+        GIVEN a response of the token endpoint to a refresh request
+        WHEN the response is read
+        THEN a rejected refresh token is reported as an invalid grant
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/token"))
+            .respond_with(ResponseTemplate::new(status).set_body_raw(body, "application/json"))
+            .mount(&mock_server)
+            .await;
+
+        let resp = reqwest::Client::new()
+            .post(format!("{}/oauth2/token", mock_server.uri()))
+            .send()
+            .await
+            .unwrap();
+
+        // WHEN
+        let actual = refresh_tokens_response(resp).await;
+
+        // THEN
+        let actual = match actual {
+            Ok(_) => "Ok",
+            Err(AuthError::InvalidGrant) => "InvalidGrant",
+            Err(AuthError::UnexpectedStatus(_, _)) => "UnexpectedStatus",
+            Err(e) => panic!("unexpected error {}", e),
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_refresh_token_rejected() {
+        let _serial = metrics::tests::serial();
+        /*
+        GIVEN a refresh token which the token endpoint rejects as an invalid grant
+        WHEN the authentication is refreshed, then started over
+        THEN the rejection is counted and the device authentication flow is pending
+        */
+
+        actix_rt::System::new().block_on(async {
+            // GIVEN
+            let mock_server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .and(path("/token"))
+                .and(body_string_contains("grant_type=refresh_token"))
+                .respond_with(
+                    ResponseTemplate::new(400)
+                        .set_body_raw(r#"{"error":"invalid_grant"}"#, "application/json"),
+                )
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("POST"))
+                .and(path("/token"))
+                .and(body_string_contains("device_code"))
+                .respond_with(
+                    ResponseTemplate::new(400)
+                        .set_body_raw(r#"{"error":"authorization_pending"}"#, "application/json"),
+                )
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("POST"))
+                .and(path("/device_authorize"))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(
+                    r#"{
+                        "device_code": "device",
+                        "expires_in": 300,
+                        "interval": 5,
+                        "user_code": "ABCD",
+                        "verification_uri_complete": "https://login.tado.com/oauth2/device?user_code=ABCD"
+                    }"#,
+                    "application/json",
+                ))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+
+            let mut client = Client::with_base_url(
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                "client_id".to_string(),
+                Box::new(MemoryTokenStore::default()),
+                HomeFilter::default(),
+            );
+            client.refresh_token = "revoked".to_string();
+            let rejections = metrics::EXPORTER_REFRESH_TOKEN_REJECTIONS.get();
+
+            // WHEN
+            let refreshed = client.refresh_authentication().await;
+            // The login is never completed, only wait for the flow to start.
+            let reauthenticated =
+                tokio::time::timeout(Duration::from_millis(500), client.reauthenticate()).await;
+
+            // THEN
+            assert!(matches!(refreshed, Err(AuthError::InvalidGrant)));
+            assert!(reauthenticated.is_err());
+            assert_eq!(
+                metrics::EXPORTER_REFRESH_TOKEN_REJECTIONS.get(),
+                rejections + 1
+            );
+            assert_eq!(
+                metrics::EXPORTER_AUTHENTICATION_PENDING
+                    .with_label_values(&[
+                        "https://login.tado.com/oauth2/device?user_code=ABCD",
+                        "ABCD"
+                    ])
+                    .get(),
+                1.0
+            );
+            assert!(client.refresh_token.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_refresh_authentication_without_refresh_token() {
+        let _serial = metrics::tests::serial();
+        /*
+        GIVEN no refresh token
+        WHEN the authentication is refreshed
+        THEN a new login is required without a request to the token endpoint
+        */
+
+        actix_rt::System::new().block_on(async {
+            // GIVEN
+            let mock_server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .and(path("/token"))
+                .respond_with(ResponseTemplate::new(400))
+                .expect(0)
+                .mount(&mock_server)
+                .await;
+
+            let mut client = Client::with_base_url(
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                "client_id".to_string(),
+                Box::new(MemoryTokenStore::default()),
+                HomeFilter::default(),
+            );
+            let rejections = metrics::EXPORTER_REFRESH_TOKEN_REJECTIONS.get();
+
+            // WHEN
+            let actual = client.refresh_authentication().await;
+
+            // THEN
+            assert!(matches!(actual, Err(AuthError::MissingRefreshToken)));
+            assert_eq!(metrics::EXPORTER_REFRESH_TOKEN_REJECTIONS.get(), rejections);
+        });
+    }
+
+    #[test]
+    fn test_authenticate_keeps_token_on_server_error() {
+        let _serial = metrics::tests::serial();
+        /*
+        GIVEN a stored token, a backup token and a token endpoint failing with a server error
+        WHEN authenticating
+        THEN the error is returned and the backup token is not sent
+        */
+
+        actix_rt::System::new().block_on(async {
+            // GIVEN
+            let mock_server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .and(path("/token"))
+                .and(body_string_contains("refresh_token=previous"))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(0)
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("POST"))
+                .and(path("/token"))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+
+            let path = token_path("test_authenticate_keeps_token_on_server_error");
+            token_file::write(&path, "previous").unwrap();
+            token_file::write(&path, "current").unwrap();
+
+            let mut client = Client::with_base_url(
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                "client_id".to_string(),
+                Box::new(FileTokenStore::new(path)),
+                HomeFilter::default(),
+            );
+
+            // WHEN
+            let actual = client.authenticate().await;
+
+            // THEN
+            assert!(matches!(actual, Err(AuthError::UnexpectedStatus(_, _))));
+            assert_eq!(client.refresh_token, "current");
+        });
+    }
+
+    #[test]
+    fn test_authenticate_without_login_on_server_error() {
+        let _serial = metrics::tests::serial();
+        /*
+        GIVEN a stored token and a token endpoint failing with a server error
+        WHEN authenticating
+        THEN the error is returned without starting the device authentication flow
+        */
+
+        actix_rt::System::new().block_on(async {
+            // GIVEN
+            let mock_server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .and(path("/token"))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("POST"))
+                .and(path("/device_authorize"))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(0)
+                .mount(&mock_server)
+                .await;
+
+            let mut token_store = MemoryTokenStore::default();
+            token_store.save("refresh").unwrap();
+
+            let mut client = Client::with_base_url(
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                mock_server.uri().parse().unwrap(),
+                "client_id".to_string(),
+                Box::new(token_store),
+                HomeFilter::default(),
+            );
+
+            // WHEN
+            let actual = client.authenticate().await;
+
+            // THEN
+            assert!(matches!(actual, Err(AuthError::UnexpectedStatus(_, _))));
+            assert!(status::STATUS
+                .read()
+                .unwrap()
+                .pending_verification_uri
+                .is_none());
+        });
+    }
+
     #[test]
     fn test_with_base_url() {
         let client = Client::with_base_url(
+            "https://example.com".parse().unwrap(),
             "https://example.com".parse().unwrap(),
             "https://example.com".parse().unwrap(),
             "client_id".to_string(),
-            ".token_file".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
         );
 
         assert_eq!(client.client_id, "client_id");
@@ -377,6 +1093,9 @@ mod tests {
                     celsius: 21.53,
                     fahrenheit: 70.75
                 },
+                weatherState: Some(WeatherStateApiResponse {
+                    value: "CLOUDY_PARTLY".to_string(),
+                }),
             }
         )
     )]
@@ -398,16 +1117,658 @@ mod tests {
             .await;
 
         let client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
             mock_server.uri().parse().unwrap(),
             mock_server.uri().parse().unwrap(),
             "client_secret".to_string(),
-            ".token_file".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
         );
 
         // WHEN
-        let actual = client.weather().await.unwrap();
+        let actual = client.weather(0).await.unwrap();
 
         // THEN
         assert_eq!(actual, expected);
     }
+
+    #[actix_rt::test]
+    async fn test_retrieve_zones_multiple_homes() {
+        /*
+        GIVEN an account with two homes
+        WHEN calling the retrieve_zones() function
+        THEN returns the zones of every home, tagged with their home
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 1, "name": "Home"}, {"id": 2, "name": "Cottage"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        for (home_id, zone_name) in [(1, "Office"), (2, "Kitchen")] {
+            let rooms = format!(
+                r#"[{{
+                    "id": 1,
+                    "name": "{zone_name}",
+                    "setting": {{ "power": "ON", "temperature": {{ "value": 21.0 }} }},
+                    "heatingPower": {{ "percentage": 38.0 }},
+                    "sensorDataPoints": {{
+                        "insideTemperature": {{
+                            "value": 23.75,
+                            "timestamp": "2022-09-03T17:43:41.088Z"
+                        }},
+                        "humidity": {{ "percentage": 40.1 }}
+                    }},
+                    "openWindow": null
+                }}]"#
+            );
+
+            Mock::given(method("GET"))
+                .and(path(format!("homes/{home_id}/rooms")))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(rooms, "application/json"))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("homes/{home_id}/zones")))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(
+                    format!(r#"[{{"id": 1, "name": "{zone_name}", "type": "HEATING"}}]"#),
+                    "application/json",
+                ))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+        let retrieved = client.retrieve_zones().await;
+
+        // THEN
+        let actual = retrieved.values;
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].home.id, 1);
+        assert_eq!(actual[0].home.name, "Home");
+        assert_eq!(actual[0].name, "Office");
+        assert_eq!(actual[1].home.id, 2);
+        assert_eq!(actual[1].home.name, "Cottage");
+        assert_eq!(actual[1].name, "Kitchen");
+
+        let inside_temperature = actual[0].sensor_data_points.inside_temperature.as_ref();
+        let humidity = actual[0].sensor_data_points.humidity.as_ref();
+        assert_eq!(inside_temperature.unwrap().timestamp, Some(1662227021.088));
+        assert_eq!(humidity.unwrap().timestamp, None);
+
+        assert!(retrieved.failed_homes.is_empty());
+        assert!(client.take_scrape_errors().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_retrieve_zones_failed_home() {
+        /*
+        GIVEN an account with two homes, the rooms of the second one failing to load
+        WHEN calling the retrieve_zones() function
+        THEN returns the zones of the first home and reports the second one as failed
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 1, "name": "Home"}, {"id": 2, "name": "Cottage"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("homes/1/rooms"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"[{
+                    "id": 1,
+                    "name": "Office",
+                    "setting": { "power": "ON", "temperature": { "value": 21.0 } },
+                    "sensorDataPoints": {},
+                    "openWindow": null
+                }]"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("homes/1/zones"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("[]", "application/json"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("homes/2/rooms"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("homes/2/zones"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("[]", "application/json"))
+            .mount(&mock_server)
+            .await;
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+        let retrieved = client.retrieve_zones().await;
+
+        // THEN
+        let actual = retrieved.values;
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].home.id, 1);
+        assert_eq!(actual[0].name, "Office");
+
+        assert_eq!(retrieved.failed_homes, vec![2]);
+        assert_eq!(client.take_scrape_errors(), vec!["zones"]);
+    }
+
+    #[rstest(include, exclude, expected,
+        case(vec![], vec![], vec![1, 2]),
+        case(vec!["Cottage"], vec![], vec![2]),
+        case(vec![], vec!["1"], vec![2]),
+    )]
+    #[actix_rt::test]
+    async fn test_retrieve_homes(include: Vec<&str>, exclude: Vec<&str>, expected: Vec<i32>) {
+        /*
+        GIVEN an account with two homes and a home filter
+        WHEN calling the retrieve_homes() function
+        THEN only the selected homes are kept, ordered by identifier
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 2, "name": "Cottage"}, {"id": 1, "name": "Home"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter {
+                include: include.iter().map(|s| s.to_string()).collect(),
+                exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            },
+        );
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+
+        // THEN
+        let actual: Vec<i32> = client.homes.unwrap().iter().map(|h| h.id).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[actix_rt::test]
+    async fn test_retrieve_homes_not_found() {
+        /*
+        GIVEN a home filter including a home which is not linked to the account
+        WHEN calling the retrieve_homes() function
+        THEN a not found error is returned
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 1, "name": "Home"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter {
+                include: vec!["42".to_string()],
+                exclude: Vec::new(),
+            },
+        );
+
+        // WHEN
+        let actual = client.retrieve_homes().await;
+
+        // THEN
+        assert!(matches!(actual, Err(HomeError::NotFound(selector)) if selector == "42"));
+        assert!(client.homes.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_retrieve_homes_none_selected() {
+        /*
+        GIVEN a home filter excluding every home of the account
+        WHEN calling the retrieve_homes() function twice
+        THEN the empty selection is kept and the homes are only requested once
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 1, "name": "Home"}]}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter {
+                include: Vec::new(),
+                exclude: vec!["1".to_string()],
+            },
+        );
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+        client.retrieve_homes().await.unwrap();
+
+        // THEN
+        assert_eq!(client.homes, Some(Vec::new()));
+    }
+
+    #[rstest(
+        stored_home_ids,
+        saved,
+        case("[1, 2]", false),
+        case("[1]", true),
+        case("[]", true)
+    )]
+    #[actix_rt::test]
+    async fn test_retrieve_homes_saves_changed_homes(stored_home_ids: &str, saved: bool) {
+        /*
+        GIVEN stored tokens recording the homes of the last run
+        WHEN calling the retrieve_homes() function
+        THEN the tokens are only saved again when the selected homes changed
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 2, "name": "Cottage"}, {"id": 1, "name": "Home"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let stored = format!(
+            r#"{{"refresh_token": "refresh", "client_id": "client_secret", "home_ids": {stored_home_ids}}}"#,
+            stored_home_ids = stored_home_ids
+        );
+        let mut token_store = MemoryTokenStore::default();
+        token_store.save(&stored).unwrap();
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(token_store),
+            HomeFilter::default(),
+        );
+        client.load_tokens().unwrap();
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+
+        // THEN
+        let actual = client.token_store.load().unwrap().unwrap();
+        assert_eq!(actual != stored, saved);
+        assert_eq!(StoredTokens::parse(&actual).unwrap().home_ids, vec![1, 2]);
+    }
+
+    #[actix_rt::test]
+    async fn test_retrieve_devices() {
+        /*
+        GIVEN a home with a valve in a zone and a bridge outside of any zone
+        WHEN calling the retrieve_devices() function
+        THEN returns both devices with their zone, battery and connection states
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 1, "name": "Home"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("homes/1/roomsAndDevices"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"
+                {
+                    "rooms": [
+                        {
+                            "roomId": 1,
+                            "roomName": "Office",
+                            "devices": [
+                                {
+                                    "serialNumber": "VA1234567890",
+                                    "type": "VA04",
+                                    "firmwareVersion": "243.1",
+                                    "connection": { "state": "CONNECTED" },
+                                    "batteryState": "LOW"
+                                }
+                            ]
+                        }
+                    ],
+                    "otherDevices": [
+                        {
+                            "serialNumber": "IB1234567890",
+                            "type": "IB02",
+                            "firmwareVersion": "245.1",
+                            "connection": { "state": "DISCONNECTED" }
+                        }
+                    ]
+                }
+                "#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+        let actual = client.retrieve_devices().await.values;
+
+        // THEN
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].zone, Some("Office".to_string()));
+        assert_eq!(actual[0].serial_number, "VA1234567890");
+        assert_eq!(actual[0].device_type, "VA04");
+        assert_eq!(actual[0].firmware_version, Some("243.1".to_string()));
+        assert_eq!(actual[0].battery_low, Some(true));
+        assert_eq!(actual[0].connected, Some(true));
+        assert_eq!(actual[1].zone, None);
+        assert_eq!(actual[1].serial_number, "IB1234567890");
+        assert_eq!(actual[1].battery_low, None);
+        assert_eq!(actual[1].connected, Some(false));
+    }
+
+    #[actix_rt::test]
+    async fn test_zones_air_conditioning() {
+        /*
+        GIVEN a home with an air conditioning zone
+        WHEN calling the zones() function
+        THEN returns the air conditioning settings and power
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("homes/1/rooms"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"
+                [{
+                    "id": 1,
+                    "name": "Bedroom",
+                    "setting": {
+                        "type": "AIR_CONDITIONING",
+                        "power": "ON",
+                        "mode": "COOL",
+                        "temperature": { "value": 19.0 },
+                        "fanLevel": "LEVEL2",
+                        "verticalSwing": "ON"
+                    },
+                    "acPower": { "value": "ON" },
+                    "sensorDataPoints": { "insideTemperature": { "value": 24.5 } }
+                }]
+                "#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        let actual = client.zones(1).await.unwrap();
+
+        // THEN
+        assert_eq!(actual.len(), 1);
+        assert_eq!(
+            actual[0].setting.r#type,
+            Some("AIR_CONDITIONING".to_string())
+        );
+        assert_eq!(actual[0].setting.mode, Some("COOL".to_string()));
+        assert_eq!(actual[0].setting.fanSpeed, Some("LEVEL2".to_string()));
+        assert_eq!(actual[0].setting.swing, Some("ON".to_string()));
+        assert_eq!(
+            actual[0].acPower,
+            Some(ActivityDataPointsAcPowerApiResponse {
+                value: "ON".to_string()
+            })
+        );
+    }
+
+    #[rstest(termination, expected,
+        case(
+            r#"{
+                "type": "TIMER",
+                "remainingTimeInSeconds": 1200,
+                "projectedExpiry": "2022-09-03T18:03:41Z"
+            }"#,
+            Some(ZoneOverlayTerminationApiResponse {
+                r#type: "TIMER".to_string(),
+                remainingTimeInSeconds: Some(1200),
+                projectedExpiry: Some("2022-09-03T18:03:41Z".to_string()),
+            })
+        ),
+        case(
+            r#"{ "type": "MANUAL", "projectedExpiry": null }"#,
+            Some(ZoneOverlayTerminationApiResponse {
+                r#type: "MANUAL".to_string(),
+                remainingTimeInSeconds: None,
+                projectedExpiry: None,
+            })
+        ),
+        case("null", None)
+    )]
+    #[actix_rt::test]
+    async fn test_zones_overlay(
+        termination: &str,
+        expected: Option<ZoneOverlayTerminationApiResponse>,
+    ) {
+        /*
+        GIVEN a home with a zone which may be manually overridden
+        WHEN calling the zones() function
+        THEN returns the termination of the manual override
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        let rooms = format!(
+            r#"[{{
+                "id": 1,
+                "name": "Office",
+                "setting": {{ "power": "ON", "temperature": {{ "value": 23.0 }} }},
+                "sensorDataPoints": {{ "insideTemperature": {{ "value": 21.5 }} }},
+                "manualControlTermination": {termination}
+            }}]"#
+        );
+
+        Mock::given(method("GET"))
+            .and(path("homes/1/rooms"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(rooms, "application/json"))
+            .mount(&mock_server)
+            .await;
+
+        let client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        let actual = client.zones(1).await.unwrap();
+
+        // THEN
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].manualControlTermination, expected);
+    }
+
+    #[actix_rt::test]
+    async fn test_retrieve_zones_hot_water() {
+        /*
+        GIVEN a home with a heating zone and a hot water zone in the zone list
+        WHEN calling the retrieve_zones() function twice
+        THEN returns the hot water zone with its setting and overlay, the zone list is only requested once
+        */
+
+        // GIVEN
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v2/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"homes": [{"id": 1, "name": "Home"}]}"#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("homes/1/rooms"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("[]", "application/json"))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("homes/1/zones"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"[
+                    {"id": 1, "name": "Living Room", "type": "HEATING"},
+                    {"id": 0, "name": "Hot Water", "type": "HOT_WATER"}
+                ]"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("homes/1/zones/0/state"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"
+                {
+                    "tadoMode": "HOME",
+                    "setting": {
+                        "type": "HOT_WATER",
+                        "power": "ON",
+                        "temperature": { "celsius": 55.0, "fahrenheit": 131.0 }
+                    },
+                    "overlayType": "MANUAL",
+                    "overlay": {
+                        "type": "MANUAL",
+                        "termination": { "type": "MANUAL" }
+                    }
+                }
+                "#,
+                "application/json",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let mut client = Client::with_base_url(
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            mock_server.uri().parse().unwrap(),
+            "client_secret".to_string(),
+            Box::new(MemoryTokenStore::default()),
+            HomeFilter::default(),
+        );
+
+        // WHEN
+        client.retrieve_homes().await.unwrap();
+        client.retrieve_zones().await;
+        let retrieved = client.retrieve_zones().await;
+
+        // THEN
+        let actual = retrieved.values;
+        assert!(retrieved.failed_homes.is_empty());
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].name, "Hot Water");
+        assert_eq!(actual[0].zone_type, ZoneType::HotWater);
+        assert!(actual[0].setting.power_on);
+        assert_eq!(actual[0].setting.temperature.as_ref().unwrap().value, 55.0);
+        assert!(actual[0].overlay.is_some());
+        assert!(client.take_scrape_errors().is_empty());
+    }
 }
@@ -1,39 +1,136 @@
+use chrono::DateTime;
+use log::warn;
+
 use super::{
     api::{
-        ActivityDataPointsHeatingPowerApiResponse, SensorDataPointsHumidityApiResponse,
-        SensorDataPointsInsideTemperatureApiResponse, WeatherApiResponse,
-        WeatherOutsideTemperatureApiResponse, WeatherSolarIntensityApiResponse,
-        ZoneStateOpenWindowApiResponse, ZoneStateSensorDataPointsApiResponse,
-        ZoneStateSettingApiResponse, ZoneStateSettingTemperatureApiResponse, ZonesApiResponse,
+        ActivityDataPointsAcPowerApiResponse, ActivityDataPointsHeatingPowerApiResponse,
+        DeviceApiResponse, HomesApiResponse, HotWaterSettingApiResponse,
+        HotWaterZoneStateApiResponse, RoomsAndDevicesApiResponse,
+        SensorDataPointsHumidityApiResponse, SensorDataPointsInsideTemperatureApiResponse,
+        WeatherApiResponse, WeatherOutsideTemperatureApiResponse, WeatherSolarIntensityApiResponse,
+        ZoneOverlayApiResponse, ZoneOverlayTerminationApiResponse, ZoneStateOpenWindowApiResponse,
+        ZoneStateSensorDataPointsApiResponse, ZoneStateSettingApiResponse,
+        ZoneStateSettingTemperatureApiResponse, ZonesApiResponse,
     },
     model::{
-        HeatingPower, Humidity, SingleTemperature, SolarIntensity, Temperature, Weather, ZoneState,
-        ZoneStateOpenWindow, ZoneStateSensorDataPoints, ZoneStateSetting,
+        AcPower, Device, HeatingPower, Home, Humidity, SensorTemperature, SingleTemperature,
+        SolarIntensity, Temperature, Weather, ZoneOverlay, ZoneState, ZoneStateOpenWindow,
+        ZoneStateSensorDataPoints, ZoneStateSetting, ZoneType,
     },
 };
 
+/// Convert an RFC 3339 timestamp returned by the API into unix seconds.
+fn parse_timestamp(value: &str) -> Option<f64> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(datetime) => Some(datetime.timestamp_millis() as f64 / 1000.0),
+        Err(e) => {
+            warn!("unable to parse timestamp {value}: {e}");
+            None
+        }
+    }
+}
+
+impl HomesApiResponse {
+    pub fn convert(&self) -> Home {
+        Home {
+            id: self.id,
+            name: self.name.clone(),
+        }
+    }
+}
+
 impl ZonesApiResponse {
-    pub fn convert(&self) -> ZoneState {
+    pub fn convert(&self, home: &Home) -> ZoneState {
         ZoneState {
+            home: home.clone(),
             name: self.name.clone(),
+            zone_type: match self.setting.r#type.as_deref() {
+                Some("AIR_CONDITIONING") => ZoneType::AirConditioning,
+                _ => ZoneType::Heating,
+            },
             setting: self.setting.convert(),
             heating_power: self.heatingPower.as_ref().map(|f| f.convert()),
+            ac_power: self.acPower.as_ref().map(|f| f.convert()),
             sensor_data_points: self.sensorDataPoints.convert(),
             open_window: self.openWindow.as_ref().map(|f| f.convert()),
+            overlay: self.manualControlTermination.as_ref().map(|f| f.convert()),
+        }
+    }
+}
+
+impl HotWaterZoneStateApiResponse {
+    pub fn convert(&self, home: &Home, name: &str) -> ZoneState {
+        ZoneState {
+            home: home.clone(),
+            name: name.to_string(),
+            zone_type: ZoneType::HotWater,
+            setting: self.setting.convert(),
+            heating_power: None,
+            ac_power: None,
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: None,
+            overlay: self.overlay.as_ref().map(|f| f.convert()),
+        }
+    }
+}
+
+impl HotWaterSettingApiResponse {
+    pub fn convert(&self) -> ZoneStateSetting {
+        ZoneStateSetting {
+            power_on: self.power == "ON",
+            temperature: self
+                .temperature
+                .as_ref()
+                .map(|f| SingleTemperature { value: f.celsius }),
+            mode: None,
+            fan_speed: None,
+            swing: None,
+        }
+    }
+}
+
+impl ZoneOverlayApiResponse {
+    pub fn convert(&self) -> ZoneOverlay {
+        match &self.termination {
+            Some(termination) => termination.convert(),
+            None => ZoneOverlay {
+                termination: self.r#type.clone(),
+                remaining_seconds: None,
+            },
+        }
+    }
+}
+
+impl ZoneOverlayTerminationApiResponse {
+    pub fn convert(&self) -> ZoneOverlay {
+        ZoneOverlay {
+            termination: self.r#type.clone(),
+            remaining_seconds: self.remainingTimeInSeconds.map(f64::from),
         }
     }
 }
 
 impl ZoneStateOpenWindowApiResponse {
     pub fn convert(&self) -> ZoneStateOpenWindow {
-        ZoneStateOpenWindow {}
+        ZoneStateOpenWindow {
+            detected_at: parse_timestamp(&self.detectedTime),
+            duration_seconds: f64::from(self.durationInSeconds),
+            remaining_seconds: f64::from(self.remainingTimeInSeconds),
+        }
     }
 }
 
 impl ZoneStateSettingApiResponse {
     pub fn convert(&self) -> ZoneStateSetting {
         ZoneStateSetting {
+            power_on: self.power == "ON",
             temperature: self.temperature.as_ref().map(|f| f.convert()),
+            mode: self.mode.clone(),
+            fan_speed: self.fanSpeed.clone(),
+            swing: self.swing.as_ref().map(|swing| swing == "ON"),
         }
     }
 }
@@ -52,6 +149,14 @@ impl ActivityDataPointsHeatingPowerApiResponse {
     }
 }
 
+impl ActivityDataPointsAcPowerApiResponse {
+    pub fn convert(&self) -> AcPower {
+        AcPower {
+            on: self.value == "ON",
+        }
+    }
+}
+
 impl ZoneStateSensorDataPointsApiResponse {
     pub fn convert(&self) -> ZoneStateSensorDataPoints {
         ZoneStateSensorDataPoints {
@@ -65,13 +170,17 @@ impl SensorDataPointsHumidityApiResponse {
     pub fn convert(&self) -> Humidity {
         Humidity {
             percentage: self.percentage,
+            timestamp: self.timestamp.as_deref().and_then(parse_timestamp),
         }
     }
 }
 
 impl SensorDataPointsInsideTemperatureApiResponse {
-    pub fn convert(&self) -> SingleTemperature {
-        SingleTemperature { value: self.value }
+    pub fn convert(&self) -> SensorTemperature {
+        SensorTemperature {
+            value: self.value,
+            timestamp: self.timestamp.as_deref().and_then(parse_timestamp),
+        }
     }
 }
 
@@ -85,10 +194,12 @@ impl WeatherOutsideTemperatureApiResponse {
 }
 
 impl WeatherApiResponse {
-    pub fn convert(&self) -> Weather {
+    pub fn convert(&self, home: &Home) -> Weather {
         Weather {
+            home: home.clone(),
             outside_temperature: self.outsideTemperature.convert(),
             solar_intensity: self.solarIntensity.convert(),
+            state: self.weatherState.as_ref().map(|f| f.value.clone()),
         }
     }
 }
@@ -100,3 +211,36 @@ impl WeatherSolarIntensityApiResponse {
         }
     }
 }
+
+impl RoomsAndDevicesApiResponse {
+    pub fn convert(&self, home: &Home) -> Vec<Device> {
+        let room_devices = self.rooms.iter().flat_map(|room| {
+            room.devices
+                .iter()
+                .map(move |device| device.convert(home, Some(room.roomName.clone())))
+        });
+        let other_devices = self
+            .otherDevices
+            .iter()
+            .map(|device| device.convert(home, None));
+
+        room_devices.chain(other_devices).collect()
+    }
+}
+
+impl DeviceApiResponse {
+    pub fn convert(&self, home: &Home, zone: Option<String>) -> Device {
+        Device {
+            home: home.clone(),
+            zone,
+            serial_number: self.serialNumber.clone(),
+            device_type: self.r#type.clone(),
+            firmware_version: self.firmwareVersion.clone(),
+            battery_low: self.batteryState.as_ref().map(|state| state != "NORMAL"),
+            connected: self
+                .connection
+                .as_ref()
+                .map(|connection| connection.state == "CONNECTED"),
+        }
+    }
+}
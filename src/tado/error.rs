@@ -9,8 +9,17 @@ pub enum AuthError {
     /// The device authentication flow took too long to complete.
     Timeout,
 
+    /// The refresh token was rejected (`invalid_grant`), e.g. because it was revoked.
+    InvalidGrant,
+
+    /// There is no refresh token, e.g. because the last login did not complete.
+    MissingRefreshToken,
+
     /// Unexpected status from the Auth API.
     UnexpectedStatus(StatusCode, Url),
+
+    /// The tokens were issued but could not be stored.
+    Storage(std::io::Error),
 }
 
 impl std::fmt::Display for AuthError {
@@ -18,9 +27,16 @@ impl std::fmt::Display for AuthError {
         match self {
             AuthError::Http(inner) => std::fmt::Display::fmt(inner, f),
             AuthError::Timeout => write!(f, "device auth flow took too long to complete"),
+            AuthError::InvalidGrant => {
+                write!(f, "refresh token was rejected, a new login is required")
+            }
+            AuthError::MissingRefreshToken => {
+                write!(f, "no refresh token, a new login is required")
+            }
             AuthError::UnexpectedStatus(status, url) => {
                 write!(f, "unexpected auth API status {status} for URL {url}")
             }
+            AuthError::Storage(inner) => write!(f, "unable to store auth tokens: {inner}"),
         }
     }
 }
@@ -30,7 +46,10 @@ impl std::error::Error for AuthError {
         match self {
             AuthError::Http(ref inner) => Some(inner),
             AuthError::Timeout => None,
+            AuthError::InvalidGrant => None,
+            AuthError::MissingRefreshToken => None,
             AuthError::UnexpectedStatus(_, _) => None,
+            AuthError::Storage(ref inner) => Some(inner),
         }
     }
 }
@@ -40,3 +59,39 @@ impl From<HttpError> for AuthError {
         AuthError::Http(value)
     }
 }
+
+/// Home selection Errors.
+#[derive(Debug)]
+pub enum HomeError {
+    /// Retrieving the homes failed because of an HTTP client error.
+    Http(HttpError),
+
+    /// A configured home is not linked to the account.
+    NotFound(String),
+}
+
+impl std::fmt::Display for HomeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HomeError::Http(inner) => std::fmt::Display::fmt(inner, f),
+            HomeError::NotFound(selector) => {
+                write!(f, "configured home {selector} is not linked to the account")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HomeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HomeError::Http(ref inner) => Some(inner),
+            HomeError::NotFound(_) => None,
+        }
+    }
+}
+
+impl From<HttpError> for HomeError {
+    fn from(value: HttpError) -> Self {
+        HomeError::Http(value)
+    }
+}
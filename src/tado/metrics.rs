@@ -1,201 +1,844 @@
+use std::collections::HashMap;
 use std::convert::Infallible;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use super::model::{Weather, ZoneState};
+use super::model::{Device, Weather, ZoneState, ZoneType};
+use super::status::{StatusZone, STATUS};
 
 use hyper::{header::CONTENT_TYPE, Body, Request, Response};
 use lazy_static::lazy_static;
 use log::info;
-use prometheus::{Encoder, GaugeVec, TextEncoder};
+use prometheus::core::Collector;
+use prometheus::{Encoder, Gauge, GaugeVec, IntCounter, IntCounterVec, TextEncoder};
+
+/// Air conditioning modes exposed by the API.
+const AC_MODES: [&str; 5] = ["COOL", "HEAT", "DRY", "FAN", "AUTO"];
+
+/// Manual override termination types exposed by the API.
+const OVERLAY_TERMINATIONS: [&str; 3] = ["MANUAL", "TIMER", "NEXT_TIME_BLOCK"];
+
+/// Weather states exposed by the API.
+const WEATHER_STATES: [&str; 18] = [
+    "CLOUDY",
+    "CLOUDY_MOSTLY",
+    "CLOUDY_PARTLY",
+    "DRIZZLE",
+    "FOGGY",
+    "FREEZING",
+    "HAIL",
+    "NIGHT_CLEAR",
+    "NIGHT_CLOUDY",
+    "RAIN",
+    "RAIN_SNOW",
+    "SCATTERED_RAIN",
+    "SCATTERED_RAIN_SNOW",
+    "SCATTERED_SNOW",
+    "SNOW",
+    "SUN",
+    "THUNDERSTORMS",
+    "WINDY",
+];
+
+lazy_static! {
+    static ref ZONE_SERIES: Mutex<Series> = Mutex::new(Series::default());
+    static ref DEVICE_SERIES: Mutex<Series> = Mutex::new(Series::default());
+    static ref WEATHER_SERIES: Mutex<Series> = Mutex::new(Series::default());
+}
 
 lazy_static! {
     pub static ref ACTIVITY_HEATING_POWER: GaugeVec = register_gauge_vec!(
         "tado_activity_heating_power_percentage",
         "The % of heating power in a specific zone.",
-        &["zone", "type"]
+        &["home_id", "home", "zone", "type"]
     )
     .unwrap();
     pub static ref ACTIVITY_AC_POWER: GaugeVec = register_gauge_vec!(
         "tado_activity_ac_power_value",
         "The value of ac power in a specific zone.",
-        &["zone", "type"]
+        &["home_id", "home", "zone", "type"]
     )
     .unwrap();
     pub static ref SETTING_TEMPERATURE: GaugeVec = register_gauge_vec!(
         "tado_setting_temperature_value",
         "The temperature of a specific zone in celsius degres.",
-        &["zone", "type", "unit"]
+        &["home_id", "home", "zone", "type", "unit"]
+    )
+    .unwrap();
+    pub static ref SETTING_POWER_ON: GaugeVec = register_gauge_vec!(
+        "tado_setting_power_on",
+        "1 if the zone is powered on, 0 if it has been switched off.",
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref SETTING_AC_MODE: GaugeVec = register_gauge_vec!(
+        "tado_setting_ac_mode",
+        "1 for the current air conditioning mode of a specific zone, 0 for the other modes.",
+        &["home_id", "home", "zone", "type", "mode"]
+    )
+    .unwrap();
+    pub static ref SETTING_AC_FAN_SPEED: GaugeVec = register_gauge_vec!(
+        "tado_setting_ac_fan_speed",
+        "The current air conditioning fan speed of a specific zone, always 1.",
+        &["home_id", "home", "zone", "type", "fan_speed"]
+    )
+    .unwrap();
+    pub static ref SETTING_AC_SWING: GaugeVec = register_gauge_vec!(
+        "tado_setting_ac_swing",
+        "1 if the air conditioning swing is on in a specific zone, 0 otherwise.",
+        &["home_id", "home", "zone", "type"]
     )
     .unwrap();
     pub static ref SENSOR_TEMPERATURE: GaugeVec = register_gauge_vec!(
         "tado_sensor_temperature_value",
         "The temperature of a specific zone in celsius degres.",
-        &["zone", "type", "unit"]
+        &["home_id", "home", "zone", "type", "unit"]
     )
     .unwrap();
     pub static ref SENSOR_HUMIDITY_PERCENTAGE: GaugeVec = register_gauge_vec!(
         "tado_sensor_humidity_percentage",
         "The % of humidity in a specific zone.",
-        &["zone", "type"]
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref SENSOR_LAST_UPDATE: GaugeVec = register_gauge_vec!(
+        "tado_sensor_last_update_timestamp_seconds",
+        "Unix timestamp of the last reading of a sensor in a specific zone.",
+        &["home_id", "home", "zone", "type", "sensor"]
     )
     .unwrap();
     pub static ref WEATHER_SOLAR_INTENSITY: GaugeVec = register_gauge_vec!(
         "weather_solar_intensity",
         "Solar intensity outside the house.",
-        &[]
+        &["home_id", "home"]
     )
     .unwrap();
     pub static ref WEATHER_OUTSIDE_TEMPERATURE: GaugeVec = register_gauge_vec!(
         "weather_outside_temperature",
         "Temperature outside the house.",
-        &["unit"]
+        &["home_id", "home", "unit"]
+    )
+    .unwrap();
+    pub static ref WEATHER_STATE: GaugeVec = register_gauge_vec!(
+        "weather_state",
+        "1 for the current weather state outside the house, 0 for the other states.",
+        &["home_id", "home", "state"]
     )
     .unwrap();
     pub static ref SENSOR_WINDOW_OPENED: GaugeVec = register_gauge_vec!(
         "tado_sensor_window_opened",
         "1 if the sensor detected a window is open, 0 otherwise.",
-        &["zone", "type"]
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref SENSOR_WINDOW_DETECTED_TIMESTAMP: GaugeVec = register_gauge_vec!(
+        "tado_sensor_window_detected_timestamp_seconds",
+        "Unix timestamp at which an open window was detected in a specific zone.",
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref SENSOR_WINDOW_DURATION: GaugeVec = register_gauge_vec!(
+        "tado_sensor_window_duration_seconds",
+        "Configured duration in seconds of the heating pause when a window is open in a specific zone.",
+        &["home_id", "home", "zone", "type"]
     )
     .unwrap();
+    pub static ref SENSOR_WINDOW_REMAINING: GaugeVec = register_gauge_vec!(
+        "tado_sensor_window_remaining_seconds",
+        "Seconds until heating resumes after an open window was detected in a specific zone.",
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref ZONE_OVERLAY_ACTIVE: GaugeVec = register_gauge_vec!(
+        "tado_zone_overlay_active",
+        "1 if the schedule of a specific zone is manually overridden, 0 otherwise.",
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref ZONE_OVERLAY_TERMINATION: GaugeVec = register_gauge_vec!(
+        "tado_zone_overlay_termination",
+        "1 for the termination type of the current manual override of a specific zone, 0 for the other types.",
+        &["home_id", "home", "zone", "type", "termination"]
+    )
+    .unwrap();
+    pub static ref ZONE_OVERLAY_REMAINING_SECONDS: GaugeVec = register_gauge_vec!(
+        "tado_zone_overlay_remaining_seconds",
+        "Seconds until the manual override of a specific zone ends and the schedule resumes.",
+        &["home_id", "home", "zone", "type"]
+    )
+    .unwrap();
+    pub static ref DEVICE_INFO: GaugeVec = register_gauge_vec!(
+        "tado_device_info",
+        "Information about a device of the home, always 1.",
+        &[
+            "home_id",
+            "home",
+            "zone",
+            "serial",
+            "device_type",
+            "firmware_version"
+        ]
+    )
+    .unwrap();
+    pub static ref DEVICE_BATTERY_LOW: GaugeVec = register_gauge_vec!(
+        "tado_device_battery_low",
+        "1 if the battery of the device is low, 0 otherwise.",
+        &["home_id", "home", "zone", "serial"]
+    )
+    .unwrap();
+    pub static ref DEVICE_CONNECTED: GaugeVec = register_gauge_vec!(
+        "tado_device_connected",
+        "1 if the device is connected, 0 otherwise.",
+        &["home_id", "home", "zone", "serial"]
+    )
+    .unwrap();
+    pub static ref EXPORTER_AUTHENTICATED: Gauge = register_gauge!(
+        "tado_exporter_authenticated",
+        "1 if the exporter holds a valid tado° access token, 0 otherwise."
+    )
+    .unwrap();
+    pub static ref EXPORTER_TOKEN_EXPIRY: Gauge = register_gauge!(
+        "tado_exporter_token_expiry_timestamp_seconds",
+        "Unix timestamp at which the tado° access token expires."
+    )
+    .unwrap();
+    pub static ref EXPORTER_AUTHENTICATION_PENDING: GaugeVec = register_gauge_vec!(
+        "tado_exporter_authentication_pending_info",
+        "Pending device authentication flow, always 1. Visit the verification URL to log in.",
+        &["verification_uri", "user_code"]
+    )
+    .unwrap();
+    pub static ref EXPORTER_SCRAPE_SUCCESS: Gauge = register_gauge!(
+        "tado_exporter_scrape_success",
+        "1 if the last scrape of the tado° API succeeded, 0 otherwise."
+    )
+    .unwrap();
+    pub static ref EXPORTER_LAST_SUCCESSFUL_SCRAPE: Gauge = register_gauge!(
+        "tado_exporter_last_successful_scrape_timestamp_seconds",
+        "Unix timestamp of the last successful scrape of the tado° API."
+    )
+    .unwrap();
+    pub static ref EXPORTER_SCRAPE_DURATION: Gauge = register_gauge!(
+        "tado_exporter_scrape_duration_seconds",
+        "Duration in seconds of the last scrape of the tado° API."
+    )
+    .unwrap();
+    pub static ref EXPORTER_REFRESH_TOKEN_REJECTIONS: IntCounter = register_int_counter!(
+        "tado_exporter_refresh_token_rejections_total",
+        "Number of times tado° rejected the refresh token, requiring a new login."
+    )
+    .unwrap();
+    pub static ref EXPORTER_SCRAPE_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "tado_exporter_scrape_errors_total",
+        "Number of failed requests to the tado° API, per endpoint.",
+        &["endpoint"]
+    )
+    .unwrap();
+}
+
+/// Series set during a poll.
+///
+/// Series which were set during the previous poll but not during the latest one
+/// (e.g. a renamed or deleted zone, or a value which became null) are removed,
+/// so they do not keep reporting their last value forever.
+///
+/// Series are keyed by metric name and label values.
+#[derive(Default)]
+struct Series(HashMap<(String, Vec<String>), &'static GaugeVec>);
+
+impl Series {
+    fn set(&mut self, gauge: &'static GaugeVec, labels: &[&str], value: f64) {
+        gauge.with_label_values(labels).set(value);
+
+        let key = (
+            gauge.desc()[0].fq_name.clone(),
+            labels.iter().map(|l| l.to_string()).collect(),
+        );
+        self.0.insert(key, gauge);
+    }
+
+    /// Replace the series of the previous poll with the latest ones, removing stale series.
+    ///
+    /// The series of homes which could not be retrieved are kept with their last value,
+    /// so a transient API error does not blank them.
+    fn replace(&mut self, mut latest: Series, failed_homes: &[i32]) {
+        let failed_homes: Vec<String> = failed_homes.iter().map(|id| id.to_string()).collect();
+
+        for (key, gauge) in self.0.drain() {
+            if latest.0.contains_key(&key) {
+                continue;
+            }
+
+            // Every series is labelled with its home identifier first.
+            if failed_homes.contains(&key.1[0]) {
+                latest.0.insert(key, gauge);
+            } else {
+                let labels: Vec<&str> = key.1.iter().map(|l| l.as_str()).collect();
+                let _ = gauge.remove_label_values(&labels);
+            }
+        }
+
+        *self = latest;
+    }
 }
 
-pub fn set_zones(zones: Vec<ZoneState>) {
+/// Set the zone metrics, keeping the series of the homes which failed to be retrieved.
+pub fn set_zones(zones: Vec<ZoneState>, failed_homes: &[i32]) {
+    let mut series = Series::default();
+    let mut status_zones = Vec::<StatusZone>::new();
+
     for zone in zones {
-        let device_type: String = "tado".to_string();
+        let zone_type: &str = zone.zone_type.label();
+        let home_id: String = zone.home.id.to_string();
+
+        status_zones.push(StatusZone {
+            home: zone.home.name.clone(),
+            name: zone.name.clone(),
+            zone_type,
+        });
 
         // The setting temperature may be null in the API response, if the
         // zone's heating mode is turned off. If the temperature setting is
-        // absent, from the API response we'll simply not set its gauge values.
-        if let Some(setting_temperature) = zone.setting.temperature {
+        // absent, from the API response we'll simply not set its gauge values
+        // and the series from the previous poll is removed.
+        if let Some(setting_temperature) = &zone.setting.temperature {
             // setting temperature
             let value: f64 = setting_temperature.value;
-            SETTING_TEMPERATURE
-                .with_label_values(&[zone.name.as_str(), device_type.as_str(), "celsius"])
-                .set(value);
+            series.set(
+                &SETTING_TEMPERATURE,
+                &[
+                    home_id.as_str(),
+                    zone.home.name.as_str(),
+                    zone.name.as_str(),
+                    zone_type,
+                    "celsius",
+                ],
+                value,
+            );
             info!(
-                "-> {} ({}) -> setting temperature (celsius): {}",
-                zone.name,
-                device_type.as_str(),
-                value
+                "-> {} -> {} ({}) -> setting temperature (celsius): {}",
+                zone.home.name, zone.name, zone_type, value
             );
         } else {
             info!(
-                "-> {} ({}) -> setting temperature (celsius): Off",
-                zone.name,
-                device_type.as_str()
+                "-> {} -> {} ({}) -> setting temperature (celsius): Off",
+                zone.home.name, zone.name, zone_type
             );
         }
 
-        // If openWindowDetected is not None, this means that a window is open.
-        if zone.open_window.is_some() {
-            info!(
-                "-> {} ({}) -> window opened: {}",
-                zone.name,
-                device_type.as_str(),
-                true
-            );
-            SENSOR_WINDOW_OPENED
-                .with_label_values(&[zone.name.as_str(), device_type.as_str()])
-                .set(1.0);
-        } else {
-            info!(
-                "-> {} ({}) -> window opened: {}",
-                zone.name,
-                device_type.as_str(),
-                false
-            );
-            SENSOR_WINDOW_OPENED
-                .with_label_values(&[zone.name.as_str(), device_type.as_str()])
-                .set(0.0);
+        // power setting, a zone switched off has no setting temperature
+        series.set(
+            &SETTING_POWER_ON,
+            &[
+                home_id.as_str(),
+                zone.home.name.as_str(),
+                zone.name.as_str(),
+                zone_type,
+            ],
+            if zone.setting.power_on { 1.0 } else { 0.0 },
+        );
+        info!(
+            "-> {} -> {} ({}) -> power on: {}",
+            zone.home.name, zone.name, zone_type, zone.setting.power_on
+        );
+
+        // Hot water zones have no open window detection.
+        if zone.zone_type != ZoneType::HotWater {
+            // If openWindowDetected is not None, this means that a window is open.
+            if zone.open_window.is_some() {
+                info!(
+                    "-> {} -> {} ({}) -> window opened: {}",
+                    zone.home.name, zone.name, zone_type, true
+                );
+                series.set(
+                    &SENSOR_WINDOW_OPENED,
+                    &[
+                        home_id.as_str(),
+                        zone.home.name.as_str(),
+                        zone.name.as_str(),
+                        zone_type,
+                    ],
+                    1.0,
+                );
+            } else {
+                info!(
+                    "-> {} -> {} ({}) -> window opened: {}",
+                    zone.home.name, zone.name, zone_type, false
+                );
+                series.set(
+                    &SENSOR_WINDOW_OPENED,
+                    &[
+                        home_id.as_str(),
+                        zone.home.name.as_str(),
+                        zone.name.as_str(),
+                        zone_type,
+                    ],
+                    0.0,
+                );
+            }
+
+            set_open_window(&mut series, &zone, &home_id, zone_type);
         }
 
         // sensor temperature
-        if let Some(inside_temperature) = zone.sensor_data_points.inside_temperature {
+        if let Some(inside_temperature) = &zone.sensor_data_points.inside_temperature {
             // celsius
             let value: f64 = inside_temperature.value;
-            SENSOR_TEMPERATURE
-                .with_label_values(&[zone.name.as_str(), device_type.as_str(), "celsius"])
-                .set(value);
+            series.set(
+                &SENSOR_TEMPERATURE,
+                &[
+                    home_id.as_str(),
+                    zone.home.name.as_str(),
+                    zone.name.as_str(),
+                    zone_type,
+                    "celsius",
+                ],
+                value,
+            );
             info!(
-                "-> {} ({}) -> sensor temperature (celsius): {}",
-                zone.name,
-                device_type.as_str(),
-                value
+                "-> {} -> {} ({}) -> sensor temperature (celsius): {}",
+                zone.home.name, zone.name, zone_type, value
             );
+
+            if let Some(timestamp) = inside_temperature.timestamp {
+                series.set(
+                    &SENSOR_LAST_UPDATE,
+                    &[
+                        home_id.as_str(),
+                        zone.home.name.as_str(),
+                        zone.name.as_str(),
+                        zone_type,
+                        "temperature",
+                    ],
+                    timestamp,
+                );
+            }
         }
 
         // sensor humidity
-        if let Some(humidity) = zone.sensor_data_points.humidity {
+        if let Some(humidity) = &zone.sensor_data_points.humidity {
             let value: f64 = humidity.percentage;
-            SENSOR_HUMIDITY_PERCENTAGE
-                .with_label_values(&[zone.name.as_str(), device_type.as_str()])
-                .set(value);
+            series.set(
+                &SENSOR_HUMIDITY_PERCENTAGE,
+                &[
+                    home_id.as_str(),
+                    zone.home.name.as_str(),
+                    zone.name.as_str(),
+                    zone_type,
+                ],
+                value,
+            );
             info!(
-                "-> {} ({}) -> sensor humidity: {}%",
-                zone.name,
-                device_type.as_str(),
-                value
+                "-> {} -> {} ({}) -> sensor humidity: {}%",
+                zone.home.name, zone.name, zone_type, value
             );
+
+            if let Some(timestamp) = humidity.timestamp {
+                series.set(
+                    &SENSOR_LAST_UPDATE,
+                    &[
+                        home_id.as_str(),
+                        zone.home.name.as_str(),
+                        zone.name.as_str(),
+                        zone_type,
+                        "humidity",
+                    ],
+                    timestamp,
+                );
+            }
         }
 
         // heating power
-        if let Some(heating_power) = zone.heating_power {
+        if let Some(heating_power) = &zone.heating_power {
             let value: f64 = heating_power.percentage;
-            ACTIVITY_HEATING_POWER
-                .with_label_values(&[zone.name.as_str(), device_type.as_str()])
-                .set(value);
+            series.set(
+                &ACTIVITY_HEATING_POWER,
+                &[
+                    home_id.as_str(),
+                    zone.home.name.as_str(),
+                    zone.name.as_str(),
+                    zone_type,
+                ],
+                value,
+            );
             info!(
-                "-> {} ({}) -> heating power: {}%",
-                zone.name,
-                device_type.as_str(),
-                value
+                "-> {} -> {} ({}) -> heating power: {}%",
+                zone.home.name, zone.name, zone_type, value
             );
         }
 
-        // // ac power
-        // if let Some(ac_power) = zone.state_response.activityDataPoints.acPower {
-        //     let value: f64 = match ac_power.value.as_str() {
-        //         "ON" => 1.0,
-        //         "OFF" => 0.0,
-        //         _ => 0.0,
-        //     };
-
-        //     ACTIVITY_AC_POWER
-        //         .with_label_values(&[zone.name.as_str(), device_type.as_str()])
-        //         .set(value);
-        //     info!(
-        //         "-> {} ({}) -> ac power: {}",
-        //         zone.name,
-        //         device_type.as_str(),
-        //         value
-        //     );
-        // }
+        // air conditioning
+        if zone.zone_type == ZoneType::AirConditioning {
+            set_air_conditioning(&mut series, &zone, &home_id, zone_type);
+        }
+
+        // manual override of the schedule
+        set_overlay(&mut series, &zone, &home_id, zone_type);
     }
+
+    ZONE_SERIES.lock().unwrap().replace(series, failed_homes);
+    STATUS.write().unwrap().zones = status_zones;
 }
 
-pub fn set_weather(weather_response: Option<Weather>) {
-    if let Some(weather) = weather_response {
+fn set_open_window(series: &mut Series, zone: &ZoneState, home_id: &str, zone_type: &str) {
+    let labels = [
+        home_id,
+        zone.home.name.as_str(),
+        zone.name.as_str(),
+        zone_type,
+    ];
+
+    // The details are only known while a window is open.
+    let open_window = match &zone.open_window {
+        Some(open_window) => open_window,
+        None => return,
+    };
+
+    if let Some(detected_at) = open_window.detected_at {
+        series.set(&SENSOR_WINDOW_DETECTED_TIMESTAMP, &labels, detected_at);
+    }
+    series.set(
+        &SENSOR_WINDOW_DURATION,
+        &labels,
+        open_window.duration_seconds,
+    );
+    series.set(
+        &SENSOR_WINDOW_REMAINING,
+        &labels,
+        open_window.remaining_seconds,
+    );
+    info!(
+        "-> {} -> {} ({}) -> window open for {}s, {}s remaining",
+        zone.home.name,
+        zone.name,
+        zone_type,
+        open_window.duration_seconds,
+        open_window.remaining_seconds
+    );
+}
+
+fn set_overlay(series: &mut Series, zone: &ZoneState, home_id: &str, zone_type: &str) {
+    let labels = [
+        home_id,
+        zone.home.name.as_str(),
+        zone.name.as_str(),
+        zone_type,
+    ];
+    let termination = zone.overlay.as_ref().map(|o| o.termination.as_str());
+
+    series.set(
+        &ZONE_OVERLAY_ACTIVE,
+        &labels,
+        if zone.overlay.is_some() { 1.0 } else { 0.0 },
+    );
+    info!(
+        "-> {} -> {} ({}) -> overlay: {}",
+        zone.home.name,
+        zone.name,
+        zone_type,
+        termination.unwrap_or("none")
+    );
+
+    // overlay termination, one series per known termination with 1 for the current one
+    for known_termination in OVERLAY_TERMINATIONS {
+        let value: f64 = if termination == Some(known_termination) {
+            1.0
+        } else {
+            0.0
+        };
+
+        series.set(
+            &ZONE_OVERLAY_TERMINATION,
+            &[
+                home_id,
+                zone.home.name.as_str(),
+                zone.name.as_str(),
+                zone_type,
+                known_termination,
+            ],
+            value,
+        );
+    }
+
+    // The remaining time is only known for overlays ending at a given time.
+    if let Some(remaining_seconds) = zone.overlay.as_ref().and_then(|o| o.remaining_seconds) {
+        series.set(&ZONE_OVERLAY_REMAINING_SECONDS, &labels, remaining_seconds);
+        info!(
+            "-> {} -> {} ({}) -> overlay remaining seconds: {}",
+            zone.home.name, zone.name, zone_type, remaining_seconds
+        );
+    }
+}
+
+fn set_air_conditioning(series: &mut Series, zone: &ZoneState, home_id: &str, zone_type: &str) {
+    let labels = [
+        home_id,
+        zone.home.name.as_str(),
+        zone.name.as_str(),
+        zone_type,
+    ];
+
+    // ac power
+    if let Some(ac_power) = &zone.ac_power {
+        let value: f64 = if ac_power.on { 1.0 } else { 0.0 };
+
+        series.set(&ACTIVITY_AC_POWER, &labels, value);
+        info!(
+            "-> {} -> {} ({}) -> ac power: {}",
+            zone.home.name, zone.name, zone_type, value
+        );
+    }
+
+    // ac mode, one series per known mode with 1 for the current one
+    if let Some(mode) = &zone.setting.mode {
+        for known_mode in AC_MODES {
+            let value: f64 = if known_mode == mode { 1.0 } else { 0.0 };
+
+            series.set(
+                &SETTING_AC_MODE,
+                &[
+                    home_id,
+                    zone.home.name.as_str(),
+                    zone.name.as_str(),
+                    zone_type,
+                    known_mode,
+                ],
+                value,
+            );
+        }
+        info!(
+            "-> {} -> {} ({}) -> ac mode: {}",
+            zone.home.name, zone.name, zone_type, mode
+        );
+    }
+
+    // ac fan speed
+    if let Some(fan_speed) = &zone.setting.fan_speed {
+        series.set(
+            &SETTING_AC_FAN_SPEED,
+            &[
+                home_id,
+                zone.home.name.as_str(),
+                zone.name.as_str(),
+                zone_type,
+                fan_speed.as_str(),
+            ],
+            1.0,
+        );
+        info!(
+            "-> {} -> {} ({}) -> ac fan speed: {}",
+            zone.home.name, zone.name, zone_type, fan_speed
+        );
+    }
+
+    // ac swing
+    if let Some(swing) = zone.setting.swing {
+        series.set(&SETTING_AC_SWING, &labels, if swing { 1.0 } else { 0.0 });
+        info!(
+            "-> {} -> {} ({}) -> ac swing: {}",
+            zone.home.name, zone.name, zone_type, swing
+        );
+    }
+}
+
+/// Set the device metrics, keeping the series of the homes which failed to be retrieved.
+pub fn set_devices(devices: Vec<Device>, failed_homes: &[i32]) {
+    let mut series = Series::default();
+
+    for device in devices {
+        let home_id: String = device.home.id.to_string();
+        // Devices which are not assigned to a zone (e.g. the bridge) get an empty zone label.
+        let zone: String = device.zone.unwrap_or_default();
+
+        series.set(
+            &DEVICE_INFO,
+            &[
+                home_id.as_str(),
+                device.home.name.as_str(),
+                zone.as_str(),
+                device.serial_number.as_str(),
+                device.device_type.as_str(),
+                device.firmware_version.as_deref().unwrap_or_default(),
+            ],
+            1.0,
+        );
+
+        if let Some(battery_low) = device.battery_low {
+            series.set(
+                &DEVICE_BATTERY_LOW,
+                &[
+                    home_id.as_str(),
+                    device.home.name.as_str(),
+                    zone.as_str(),
+                    device.serial_number.as_str(),
+                ],
+                if battery_low { 1.0 } else { 0.0 },
+            );
+            info!(
+                "-> {} -> {} ({}) -> battery low: {}",
+                device.home.name, device.serial_number, device.device_type, battery_low
+            );
+        }
+
+        if let Some(connected) = device.connected {
+            series.set(
+                &DEVICE_CONNECTED,
+                &[
+                    home_id.as_str(),
+                    device.home.name.as_str(),
+                    zone.as_str(),
+                    device.serial_number.as_str(),
+                ],
+                if connected { 1.0 } else { 0.0 },
+            );
+            info!(
+                "-> {} -> {} ({}) -> connected: {}",
+                device.home.name, device.serial_number, device.device_type, connected
+            );
+        }
+    }
+
+    DEVICE_SERIES.lock().unwrap().replace(series, failed_homes);
+}
+
+/// Set the weather metrics, keeping the series of the homes which failed to be retrieved.
+pub fn set_weather(weathers: Vec<Weather>, failed_homes: &[i32]) {
+    let mut series = Series::default();
+
+    for weather in weathers {
+        let home_id: String = weather.home.id.to_string();
+
         // setting solar intensity
         let solar_intensity_percentage = weather.solar_intensity.percentage;
 
-        WEATHER_SOLAR_INTENSITY
-            .with_label_values(&[])
-            .set(weather.solar_intensity.percentage);
-        info!("-> setting solar intensity (percentage): {solar_intensity_percentage}");
+        series.set(
+            &WEATHER_SOLAR_INTENSITY,
+            &[home_id.as_str(), weather.home.name.as_str()],
+            weather.solar_intensity.percentage,
+        );
+        info!(
+            "-> {} -> setting solar intensity (percentage): {solar_intensity_percentage}",
+            weather.home.name
+        );
 
         // setting outside temperature
         let outside_temperature_celsius = weather.outside_temperature.celsius;
         let outside_temperature_fahrenheit = weather.outside_temperature.fahrenheit;
 
-        WEATHER_OUTSIDE_TEMPERATURE
-            .with_label_values(&["celsius"])
-            .set(outside_temperature_celsius);
-        info!("-> setting outside temperature (celsius): {outside_temperature_celsius}");
+        series.set(
+            &WEATHER_OUTSIDE_TEMPERATURE,
+            &[home_id.as_str(), weather.home.name.as_str(), "celsius"],
+            outside_temperature_celsius,
+        );
+        info!(
+            "-> {} -> setting outside temperature (celsius): {outside_temperature_celsius}",
+            weather.home.name
+        );
+
+        series.set(
+            &WEATHER_OUTSIDE_TEMPERATURE,
+            &[home_id.as_str(), weather.home.name.as_str(), "fahrenheit"],
+            outside_temperature_fahrenheit,
+        );
+        info!(
+            "-> {} -> setting outside temperature (fahrenheit): {outside_temperature_fahrenheit}",
+            weather.home.name
+        );
+
+        // setting weather state, one series per known state with 1 for the current one
+        if let Some(state) = &weather.state {
+            let states = WEATHER_STATES
+                .iter()
+                .copied()
+                .chain((!WEATHER_STATES.contains(&state.as_str())).then_some(state.as_str()));
+
+            for known_state in states {
+                let value: f64 = if known_state == state { 1.0 } else { 0.0 };
 
-        WEATHER_OUTSIDE_TEMPERATURE
-            .with_label_values(&["fahrenheit"])
-            .set(outside_temperature_fahrenheit);
-        info!("-> setting outside temperature (fahrenheit): {outside_temperature_fahrenheit}");
+                series.set(
+                    &WEATHER_STATE,
+                    &[home_id.as_str(), weather.home.name.as_str(), known_state],
+                    value,
+                );
+            }
+            info!("-> {} -> setting weather state: {state}", weather.home.name);
+        }
     }
+
+    WEATHER_SERIES.lock().unwrap().replace(series, failed_homes);
+}
+
+/// Record that a device authentication flow waits for the user to log in.
+pub fn set_authentication_pending(verification_uri: &str, user_code: &str) {
+    EXPORTER_AUTHENTICATED.set(0.0);
+    EXPORTER_AUTHENTICATION_PENDING.reset();
+    EXPORTER_AUTHENTICATION_PENDING
+        .with_label_values(&[verification_uri, user_code])
+        .set(1.0);
+
+    let mut status = STATUS.write().unwrap();
+    status.authenticated = false;
+    status.pending_verification_uri = Some(verification_uri.to_string());
+}
+
+/// Record that the exporter holds an access token valid until the given time.
+pub fn set_authenticated(expires_at: SystemTime) {
+    EXPORTER_AUTHENTICATED.set(1.0);
+    EXPORTER_TOKEN_EXPIRY.set(unix_seconds(expires_at));
+    EXPORTER_AUTHENTICATION_PENDING.reset();
+
+    let mut status = STATUS.write().unwrap();
+    status.authenticated = true;
+    status.pending_verification_uri = None;
+}
+
+/// Record that the exporter has no valid access token and no pending login.
+pub fn set_unauthenticated() {
+    EXPORTER_AUTHENTICATED.set(0.0);
+    EXPORTER_AUTHENTICATION_PENDING.reset();
+
+    let mut status = STATUS.write().unwrap();
+    status.authenticated = false;
+    status.pending_verification_uri = None;
+}
+
+/// Record that the refresh token was rejected, so a new login is required.
+pub fn set_refresh_token_rejected() {
+    EXPORTER_REFRESH_TOKEN_REJECTIONS.inc();
+    set_unauthenticated();
+}
+
+/// Record the outcome of a scrape, given the endpoints which failed during it.
+pub fn set_scrape_result(errors: &[&str], duration: Duration) {
+    for endpoint in errors {
+        EXPORTER_SCRAPE_ERRORS.with_label_values(&[endpoint]).inc();
+    }
+
+    EXPORTER_SCRAPE_DURATION.set(duration.as_secs_f64());
+
+    let now = SystemTime::now();
+    let mut status = STATUS.write().unwrap();
+    status.last_scrape = Some(now);
+
+    if errors.is_empty() {
+        EXPORTER_SCRAPE_SUCCESS.set(1.0);
+        EXPORTER_LAST_SUCCESSFUL_SCRAPE.set(unix_seconds(now));
+        status.last_successful_scrape = Some(now);
+    } else {
+        EXPORTER_SCRAPE_SUCCESS.set(0.0);
+    }
+    info!(
+        "-> scrape completed in {:.3}s with {} error(s)",
+        duration.as_secs_f64(),
+        errors.len()
+    );
+}
+
+/// Convert a time into a unix timestamp in seconds.
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
 }
 
 pub async fn renderer(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
@@ -215,13 +858,37 @@ pub async fn renderer(_req: Request<Body>) -> Result<Response<Body>, Infallible>
 }
 
 #[cfg(test)]
-mod tests {
-    use crate::tado::model::{SolarIntensity, Temperature, Weather};
+pub(crate) mod tests {
+    use crate::tado::model::{
+        AcPower, Home, SingleTemperature, SolarIntensity, Temperature, Weather, ZoneOverlay,
+        ZoneStateOpenWindow, ZoneStateSensorDataPoints, ZoneStateSetting,
+    };
+    use prometheus::proto::MetricFamily;
+    use std::sync::MutexGuard;
 
     use super::*;
 
+    lazy_static! {
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    /// Run the tests one at a time, as each poll removes the series of the previous one.
+    /// Tests of other modules updating the metrics use it as well.
+    pub(crate) fn serial() -> MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Gather the metric families whose name starts with the given prefix.
+    fn gather(prefix: &str) -> Vec<MetricFamily> {
+        prometheus::gather()
+            .into_iter()
+            .filter(|m| m.get_name().starts_with(prefix))
+            .collect()
+    }
+
     #[test]
     fn test_set_weather_some() {
+        let _serial = serial();
         prometheus::gather();
         /*
         GIVEN a weather response
@@ -231,23 +898,29 @@ mod tests {
 
         // GIVEN
         let weather_response = Weather {
+            home: Home {
+                id: 1,
+                name: "Home".to_string(),
+            },
             solar_intensity: SolarIntensity { percentage: 100.0 },
             outside_temperature: Temperature {
                 celsius: 20.0,
                 fahrenheit: 68.0,
             },
+            state: Some("SUN".to_string()),
         };
 
         // WHEN
-        set_weather(Some(weather_response));
+        set_weather(vec![weather_response], &[]);
 
         // THEN
         // Check metrics
-        let metrics = prometheus::gather();
+        let metrics = gather("weather_");
 
-        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics.len(), 3);
         assert_eq!(metrics[0].get_name(), "weather_outside_temperature");
         assert_eq!(metrics[1].get_name(), "weather_solar_intensity");
+        assert_eq!(metrics[2].get_name(), "weather_state");
 
         // Check outside temperature metric
         let outside_temperature_metric = metrics[0].get_metric();
@@ -257,14 +930,18 @@ mod tests {
         let outside_temp_celsius = &outside_temperature_metric[0];
         let outside_temp_fahrenheit = &outside_temperature_metric[1];
 
-        assert_eq!(outside_temp_celsius.get_label().len(), 1);
-        assert_eq!(outside_temp_celsius.get_label()[0].get_name(), "unit");
-        assert_eq!(outside_temp_celsius.get_label()[0].get_value(), "celsius");
+        assert_eq!(outside_temp_celsius.get_label().len(), 3);
+        assert_eq!(outside_temp_celsius.get_label()[0].get_name(), "home");
+        assert_eq!(outside_temp_celsius.get_label()[0].get_value(), "Home");
+        assert_eq!(outside_temp_celsius.get_label()[1].get_name(), "home_id");
+        assert_eq!(outside_temp_celsius.get_label()[1].get_value(), "1");
+        assert_eq!(outside_temp_celsius.get_label()[2].get_name(), "unit");
+        assert_eq!(outside_temp_celsius.get_label()[2].get_value(), "celsius");
         assert_eq!(outside_temp_celsius.get_gauge().get_value(), 20.0);
-        assert_eq!(outside_temp_fahrenheit.get_label().len(), 1);
-        assert_eq!(outside_temp_fahrenheit.get_label()[0].get_name(), "unit");
+        assert_eq!(outside_temp_fahrenheit.get_label().len(), 3);
+        assert_eq!(outside_temp_fahrenheit.get_label()[2].get_name(), "unit");
         assert_eq!(
-            outside_temp_fahrenheit.get_label()[0].get_value(),
+            outside_temp_fahrenheit.get_label()[2].get_value(),
             "fahrenheit"
         );
         assert_eq!(outside_temp_fahrenheit.get_gauge().get_value(), 68.0);
@@ -274,10 +951,18 @@ mod tests {
 
         assert_eq!(solar_intensity_metric.len(), 1);
         assert_eq!(solar_intensity_metric[0].get_gauge().get_value(), 100.0);
+
+        // Check weather state metric
+        let state = |state| WEATHER_STATE.with_label_values(&["1", "Home", state]).get();
+
+        assert_eq!(metrics[2].get_metric().len(), WEATHER_STATES.len());
+        assert_eq!(state("SUN"), 1.0);
+        assert_eq!(state("RAIN"), 0.0);
     }
 
     #[test]
     fn test_set_weather_none() {
+        let _serial = serial();
         prometheus::gather();
         /*
         GIVEN no weather response
@@ -286,11 +971,451 @@ mod tests {
         */
 
         // WHEN
-        set_weather(None);
+        set_weather(Vec::new(), &[]);
 
         // THEN
-        let metrics = prometheus::gather();
+        let metrics = gather("weather_");
 
         assert_eq!(metrics.len(), 0);
     }
+
+    #[test]
+    fn test_set_zones_power_off() {
+        let _serial = serial();
+        /*
+        GIVEN a zone which has been switched off
+        WHEN set_zones is called
+        THEN the power gauge is set to 0
+        */
+
+        // GIVEN
+        let zone = ZoneState {
+            home: Home {
+                id: 4,
+                name: "Power".to_string(),
+            },
+            name: "Office".to_string(),
+            zone_type: ZoneType::Heating,
+            setting: ZoneStateSetting {
+                power_on: false,
+                temperature: None,
+                mode: None,
+                fan_speed: None,
+                swing: None,
+            },
+            heating_power: None,
+            ac_power: None,
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: None,
+            overlay: None,
+        };
+
+        // WHEN
+        set_zones(vec![zone], &[]);
+
+        // THEN
+        let value = SETTING_POWER_ON
+            .get_metric_with_label_values(&["4", "Power", "Office", "heating"])
+            .unwrap()
+            .get();
+
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn test_set_zones_air_conditioning() {
+        let _serial = serial();
+        /*
+        GIVEN an air conditioning zone cooling with swing enabled
+        WHEN set_zones is called
+        THEN the air conditioning gauges are set
+        */
+
+        // GIVEN
+        let zone = ZoneState {
+            home: Home {
+                id: 5,
+                name: "AirConditioning".to_string(),
+            },
+            name: "Bedroom".to_string(),
+            zone_type: ZoneType::AirConditioning,
+            setting: ZoneStateSetting {
+                power_on: true,
+                temperature: None,
+                mode: Some("COOL".to_string()),
+                fan_speed: Some("LEVEL2".to_string()),
+                swing: Some(true),
+            },
+            heating_power: None,
+            ac_power: Some(AcPower { on: true }),
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: None,
+            overlay: None,
+        };
+
+        // WHEN
+        set_zones(vec![zone], &[]);
+
+        // THEN
+        let labels = ["5", "AirConditioning", "Bedroom", "air_conditioning"];
+        let mode = |mode| {
+            SETTING_AC_MODE
+                .get_metric_with_label_values(&[
+                    "5",
+                    "AirConditioning",
+                    "Bedroom",
+                    "air_conditioning",
+                    mode,
+                ])
+                .unwrap()
+                .get()
+        };
+
+        assert_eq!(ACTIVITY_AC_POWER.with_label_values(&labels).get(), 1.0);
+        assert_eq!(mode("COOL"), 1.0);
+        assert_eq!(mode("HEAT"), 0.0);
+        assert_eq!(
+            SETTING_AC_FAN_SPEED
+                .with_label_values(&[
+                    "5",
+                    "AirConditioning",
+                    "Bedroom",
+                    "air_conditioning",
+                    "LEVEL2"
+                ])
+                .get(),
+            1.0
+        );
+        assert_eq!(SETTING_AC_SWING.with_label_values(&labels).get(), 1.0);
+    }
+
+    #[test]
+    fn test_set_zones_overlay() {
+        let _serial = serial();
+        /*
+        GIVEN a zone manually overridden until a timer ends
+        WHEN set_zones is called
+        THEN the overlay gauges are set
+        */
+
+        // GIVEN
+        let zone = ZoneState {
+            home: Home {
+                id: 6,
+                name: "Overlay".to_string(),
+            },
+            name: "Office".to_string(),
+            zone_type: ZoneType::Heating,
+            setting: ZoneStateSetting {
+                power_on: true,
+                temperature: None,
+                mode: None,
+                fan_speed: None,
+                swing: None,
+            },
+            heating_power: None,
+            ac_power: None,
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: None,
+            overlay: Some(ZoneOverlay {
+                termination: "TIMER".to_string(),
+                remaining_seconds: Some(1800.0),
+            }),
+        };
+
+        // WHEN
+        set_zones(vec![zone], &[]);
+
+        // THEN
+        let labels = ["6", "Overlay", "Office", "heating"];
+        let termination = |termination| {
+            ZONE_OVERLAY_TERMINATION
+                .with_label_values(&["6", "Overlay", "Office", "heating", termination])
+                .get()
+        };
+
+        assert_eq!(ZONE_OVERLAY_ACTIVE.with_label_values(&labels).get(), 1.0);
+        assert_eq!(termination("TIMER"), 1.0);
+        assert_eq!(termination("MANUAL"), 0.0);
+        assert_eq!(
+            ZONE_OVERLAY_REMAINING_SECONDS
+                .with_label_values(&labels)
+                .get(),
+            1800.0
+        );
+    }
+
+    #[test]
+    fn test_set_zones_open_window() {
+        let _serial = serial();
+        /*
+        GIVEN a zone in which an open window was detected
+        WHEN set_zones is called
+        THEN the open window gauges are set
+        */
+
+        // GIVEN
+        let zone = ZoneState {
+            home: Home {
+                id: 7,
+                name: "OpenWindow".to_string(),
+            },
+            name: "Office".to_string(),
+            zone_type: ZoneType::Heating,
+            setting: ZoneStateSetting {
+                power_on: true,
+                temperature: None,
+                mode: None,
+                fan_speed: None,
+                swing: None,
+            },
+            heating_power: None,
+            ac_power: None,
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: Some(ZoneStateOpenWindow {
+                detected_at: Some(1662226800.0),
+                duration_seconds: 900.0,
+                remaining_seconds: 600.0,
+            }),
+            overlay: None,
+        };
+
+        // WHEN
+        set_zones(vec![zone], &[]);
+
+        // THEN
+        let labels = ["7", "OpenWindow", "Office", "heating"];
+
+        assert_eq!(SENSOR_WINDOW_OPENED.with_label_values(&labels).get(), 1.0);
+        assert_eq!(
+            SENSOR_WINDOW_DETECTED_TIMESTAMP
+                .with_label_values(&labels)
+                .get(),
+            1662226800.0
+        );
+        assert_eq!(
+            SENSOR_WINDOW_DURATION.with_label_values(&labels).get(),
+            900.0
+        );
+        assert_eq!(
+            SENSOR_WINDOW_REMAINING.with_label_values(&labels).get(),
+            600.0
+        );
+    }
+
+    #[test]
+    fn test_set_zones_removes_stale_series() {
+        let _serial = serial();
+        /*
+        GIVEN a zone which was reported by a previous poll
+        WHEN set_zones is called without it, and with a zone which has no setting temperature
+        THEN the series of the missing zone and of the null temperature are removed
+        */
+
+        // GIVEN
+        let zone = |name: &str, temperature: Option<f64>| ZoneState {
+            home: Home {
+                id: 8,
+                name: "Stale".to_string(),
+            },
+            name: name.to_string(),
+            zone_type: ZoneType::Heating,
+            setting: ZoneStateSetting {
+                power_on: temperature.is_some(),
+                temperature: temperature.map(|value| SingleTemperature { value }),
+                mode: None,
+                fan_speed: None,
+                swing: None,
+            },
+            heating_power: None,
+            ac_power: None,
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: None,
+            overlay: None,
+        };
+        set_zones(
+            vec![zone("Office", Some(21.0)), zone("Kitchen", Some(20.0))],
+            &[],
+        );
+
+        // WHEN
+        set_zones(vec![zone("Kitchen", None)], &[]);
+
+        // THEN
+        let office = ["8", "Stale", "Office", "heating"];
+        let kitchen = ["8", "Stale", "Kitchen", "heating"];
+
+        assert!(SETTING_POWER_ON.remove_label_values(&office).is_err());
+        assert!(SETTING_TEMPERATURE
+            .remove_label_values(&["8", "Stale", "Office", "heating", "celsius"])
+            .is_err());
+        assert!(SETTING_TEMPERATURE
+            .remove_label_values(&["8", "Stale", "Kitchen", "heating", "celsius"])
+            .is_err());
+        assert_eq!(SETTING_POWER_ON.with_label_values(&kitchen).get(), 0.0);
+    }
+
+    #[test]
+    fn test_set_zones_keeps_series_of_failed_homes() {
+        let _serial = serial();
+        /*
+        GIVEN zones of two homes reported by a previous poll
+        WHEN set_zones is called without the zones of a home which failed to be retrieved
+        THEN the series of the failed home keep their last value
+        */
+
+        // GIVEN
+        let zone = |home_id: i32| ZoneState {
+            home: Home {
+                id: home_id,
+                name: "Failed".to_string(),
+            },
+            name: "Office".to_string(),
+            zone_type: ZoneType::Heating,
+            setting: ZoneStateSetting {
+                power_on: true,
+                temperature: Some(SingleTemperature { value: 21.0 }),
+                mode: None,
+                fan_speed: None,
+                swing: None,
+            },
+            heating_power: None,
+            ac_power: None,
+            sensor_data_points: ZoneStateSensorDataPoints {
+                inside_temperature: None,
+                humidity: None,
+            },
+            open_window: None,
+            overlay: None,
+        };
+        set_zones(vec![zone(10), zone(11)], &[]);
+
+        // WHEN
+        set_zones(vec![zone(10)], &[11]);
+        set_zones(vec![zone(10)], &[11]);
+
+        // THEN
+        let labels = |home_id: &'static str| [home_id, "Failed", "Office", "heating", "celsius"];
+
+        assert_eq!(
+            SETTING_TEMPERATURE.with_label_values(&labels("10")).get(),
+            21.0
+        );
+        assert_eq!(
+            SETTING_TEMPERATURE.with_label_values(&labels("11")).get(),
+            21.0
+        );
+
+        // the series are removed once the home is retrieved without them
+        set_zones(vec![zone(10)], &[]);
+        assert!(SETTING_TEMPERATURE
+            .remove_label_values(&labels("11"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_set_scrape_result() {
+        let _serial = serial();
+        /*
+        GIVEN a successful scrape followed by a failed scrape
+        WHEN set_scrape_result is called
+        THEN the scrape success, timestamp, duration and error metrics are set
+        */
+
+        // GIVEN
+        let errors_before = EXPORTER_SCRAPE_ERRORS.with_label_values(&["zones"]).get();
+
+        // WHEN
+        set_scrape_result(&[], Duration::from_millis(1500));
+
+        // THEN
+        let last_success = EXPORTER_LAST_SUCCESSFUL_SCRAPE.get();
+
+        assert_eq!(EXPORTER_SCRAPE_SUCCESS.get(), 1.0);
+        assert_eq!(EXPORTER_SCRAPE_DURATION.get(), 1.5);
+        assert!(last_success > 0.0);
+
+        // WHEN
+        set_scrape_result(&["zones", "zones"], Duration::from_millis(500));
+
+        // THEN
+        assert_eq!(EXPORTER_SCRAPE_SUCCESS.get(), 0.0);
+        assert_eq!(EXPORTER_SCRAPE_DURATION.get(), 0.5);
+        assert_eq!(EXPORTER_LAST_SUCCESSFUL_SCRAPE.get(), last_success);
+        assert_eq!(
+            EXPORTER_SCRAPE_ERRORS.with_label_values(&["zones"]).get(),
+            errors_before + 2
+        );
+    }
+
+    #[test]
+    fn test_set_authentication_state() {
+        let _serial = serial();
+        /*
+        GIVEN a pending device authentication flow
+        WHEN the flow completes
+        THEN the pending flow is cleared and the token expiry is set
+        */
+
+        // GIVEN
+        set_authentication_pending(
+            "https://login.tado.com/oauth2/device?user_code=ABCD",
+            "ABCD",
+        );
+
+        assert_eq!(EXPORTER_AUTHENTICATED.get(), 0.0);
+        assert_eq!(
+            EXPORTER_AUTHENTICATION_PENDING
+                .with_label_values(&[
+                    "https://login.tado.com/oauth2/device?user_code=ABCD",
+                    "ABCD"
+                ])
+                .get(),
+            1.0
+        );
+
+        // WHEN
+        set_authenticated(UNIX_EPOCH + Duration::from_secs(1662227021));
+
+        // THEN
+        assert_eq!(EXPORTER_AUTHENTICATED.get(), 1.0);
+        assert_eq!(EXPORTER_TOKEN_EXPIRY.get(), 1662227021.0);
+        assert_eq!(gather("tado_exporter_authentication_pending_info").len(), 0);
+    }
+
+    #[test]
+    fn test_set_refresh_token_rejected() {
+        let _serial = serial();
+        /*
+        GIVEN an authenticated exporter
+        WHEN the refresh token is rejected
+        THEN the rejection is counted and the exporter is no longer authenticated
+        */
+
+        // GIVEN
+        set_authenticated(UNIX_EPOCH + Duration::from_secs(1662227021));
+        let rejections = EXPORTER_REFRESH_TOKEN_REJECTIONS.get();
+
+        // WHEN
+        set_refresh_token_rejected();
+
+        // THEN
+        assert_eq!(EXPORTER_REFRESH_TOKEN_REJECTIONS.get(), rejections + 1);
+        assert_eq!(EXPORTER_AUTHENTICATED.get(), 0.0);
+    }
 }
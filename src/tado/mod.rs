@@ -4,3 +4,6 @@ pub mod convert;
 pub mod error;
 pub mod metrics;
 pub mod model;
+pub mod status;
+pub mod token_file;
+pub mod token_store;
@@ -1,6 +1,54 @@
+#[derive(Clone, Debug, PartialEq)]
+pub struct Home {
+    pub id: i32,
+    pub name: String,
+}
+
+impl Home {
+    /// Whether the home is designated by the given identifier or name.
+    pub fn matches(&self, selector: &str) -> bool {
+        selector == self.id.to_string() || selector == self.name
+    }
+}
+
+/// Selection of the homes to scrape, by identifier or name.
+///
+/// An empty include list selects every home of the account.
+#[derive(Clone, Debug, Default)]
+pub struct HomeFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl HomeFilter {
+    pub fn allows(&self, home: &Home) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|s| home.matches(s)))
+            && !self.exclude.iter().any(|s| home.matches(s))
+    }
+}
+
+/// Values retrieved for the selected homes during a poll.
+pub struct Retrieved<T> {
+    pub values: Vec<T>,
+    /// Homes for which a request failed, their values may be missing or partial.
+    pub failed_homes: Vec<i32>,
+}
+
+impl<T> Default for Retrieved<T> {
+    fn default() -> Self {
+        Retrieved {
+            values: Vec::new(),
+            failed_homes: Vec::new(),
+        }
+    }
+}
+
 pub struct Weather {
+    pub home: Home,
     pub solar_intensity: SolarIntensity,
     pub outside_temperature: Temperature,
+    /// Current weather state, e.g. SUN, CLOUDY_PARTLY or RAIN.
+    pub state: Option<String>,
 }
 
 pub struct Temperature {
@@ -18,27 +66,89 @@ pub struct SolarIntensity {
 
 pub struct Humidity {
     pub percentage: f64,
+    /// Time of the reading, as a unix timestamp in seconds.
+    pub timestamp: Option<f64>,
 }
 
 pub struct HeatingPower {
     pub percentage: f64,
 }
 
+pub struct AcPower {
+    pub on: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoneType {
+    Heating,
+    AirConditioning,
+    HotWater,
+}
+
+impl ZoneType {
+    /// Value of the `type` label of the zone metrics.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ZoneType::Heating => "heating",
+            ZoneType::AirConditioning => "air_conditioning",
+            ZoneType::HotWater => "hot_water",
+        }
+    }
+}
+
 pub struct ZoneState {
+    pub home: Home,
     pub name: String,
+    pub zone_type: ZoneType,
     pub setting: ZoneStateSetting,
     pub heating_power: Option<HeatingPower>,
+    pub ac_power: Option<AcPower>,
     pub sensor_data_points: ZoneStateSensorDataPoints,
     pub open_window: Option<ZoneStateOpenWindow>,
+    pub overlay: Option<ZoneOverlay>,
 }
 
 pub struct ZoneStateSetting {
+    pub power_on: bool,
     pub temperature: Option<SingleTemperature>,
+    // Air conditioning settings
+    pub mode: Option<String>,
+    pub fan_speed: Option<String>,
+    pub swing: Option<bool>,
 }
 
-pub struct ZoneStateOpenWindow {}
+pub struct ZoneStateOpenWindow {
+    /// Detection time, as a unix timestamp in seconds.
+    pub detected_at: Option<f64>,
+    pub duration_seconds: f64,
+    pub remaining_seconds: f64,
+}
+
+/// A manual override of the zone schedule.
+pub struct ZoneOverlay {
+    /// How the override ends: MANUAL, TIMER or NEXT_TIME_BLOCK.
+    pub termination: String,
+    pub remaining_seconds: Option<f64>,
+}
+
+pub struct SensorTemperature {
+    pub value: f64,
+    /// Time of the reading, as a unix timestamp in seconds.
+    pub timestamp: Option<f64>,
+}
 
 pub struct ZoneStateSensorDataPoints {
-    pub inside_temperature: Option<SingleTemperature>,
+    pub inside_temperature: Option<SensorTemperature>,
     pub humidity: Option<Humidity>,
 }
+
+pub struct Device {
+    pub home: Home,
+    /// Name of the zone the device is assigned to, if any.
+    pub zone: Option<String>,
+    pub serial_number: String,
+    pub device_type: String,
+    pub firmware_version: Option<String>,
+    pub battery_low: Option<bool>,
+    pub connected: Option<bool>,
+}
@@ -0,0 +1,205 @@
+use std::convert::Infallible;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use hyper::{header::CONTENT_TYPE, Body, Request, Response, StatusCode};
+use lazy_static::lazy_static;
+
+/// State of the exporter, displayed on the status page.
+#[derive(Default)]
+pub struct Status {
+    pub authenticated: bool,
+    /// Verification URL of the pending device authentication flow, if any.
+    pub pending_verification_uri: Option<String>,
+    pub last_scrape: Option<SystemTime>,
+    pub last_successful_scrape: Option<SystemTime>,
+    pub zones: Vec<StatusZone>,
+}
+
+impl Status {
+    /// The exporter is ready once authenticated and at least one scrape completed.
+    pub fn is_ready(&self) -> bool {
+        self.authenticated && self.last_scrape.is_some()
+    }
+}
+
+pub struct StatusZone {
+    pub home: String,
+    pub name: String,
+    pub zone_type: &'static str,
+}
+
+lazy_static! {
+    pub static ref STATUS: RwLock<Status> = RwLock::new(Status::default());
+}
+
+pub async fn renderer(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let body = render(&STATUS.read().unwrap());
+
+    let response = Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap();
+
+    Ok(response)
+}
+
+/// Liveness probe, answers as long as the HTTP server is running.
+pub async fn liveness(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    Ok(plain_text(StatusCode::OK, "ok"))
+}
+
+/// Readiness probe, see [`Status::is_ready`].
+pub async fn readiness(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if STATUS.read().unwrap().is_ready() {
+        Ok(plain_text(StatusCode::OK, "ready"))
+    } else {
+        Ok(plain_text(StatusCode::SERVICE_UNAVAILABLE, "not ready"))
+    }
+}
+
+pub async fn not_found(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    Ok(plain_text(StatusCode::NOT_FOUND, "not found"))
+}
+
+fn plain_text(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn render(status: &Status) -> String {
+    let authentication = match &status.pending_verification_uri {
+        Some(uri) => format!(
+            "Login required: <a href=\"{0}\">{0}</a>",
+            escape(uri.as_str())
+        ),
+        None if status.authenticated => "Authenticated".to_string(),
+        None => "Not authenticated".to_string(),
+    };
+
+    let zones: String = status
+        .zones
+        .iter()
+        .map(|zone| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(&zone.home),
+                escape(&zone.name),
+                zone.zone_type
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head><title>tado° exporter</title></head>
+<body>
+<h1>tado° exporter</h1>
+<p>Authentication: {authentication}</p>
+<p>Last scrape: {}</p>
+<p>Last successful scrape: {}</p>
+<p><a href=\"/metrics\">Metrics</a></p>
+<h2>Zones</h2>
+<table>
+<tr><th>Home</th><th>Zone</th><th>Type</th></tr>
+{zones}</table>
+</body>
+</html>
+",
+        format_time(status.last_scrape),
+        format_time(status.last_successful_scrape),
+    )
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    match time {
+        Some(time) => DateTime::<Utc>::from(time).to_rfc3339(),
+        None => "never".to_string(),
+    }
+}
+
+/// Escape a value to be displayed in HTML.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[rstest]
+    #[case(false, None, false)]
+    #[case(true, None, false)]
+    #[case(false, Some(UNIX_EPOCH), false)]
+    #[case(true, Some(UNIX_EPOCH), true)]
+    fn test_is_ready(
+        #[case] authenticated: bool,
+        #[case] last_scrape: Option<SystemTime>,
+        #[case] expected: bool,
+    ) {
+        /*
+        GIVEN an authentication state and an optional last scrape
+        WHEN readiness is checked
+        THEN the exporter is only ready when authenticated and scraped at least once
+        */
+
+        // GIVEN
+        let status = Status {
+            authenticated,
+            last_scrape,
+            ..Status::default()
+        };
+
+        // WHEN
+        let actual = status.is_ready();
+
+        // THEN
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_render_pending_login() {
+        /*
+        GIVEN a pending device authentication flow and a known zone
+        WHEN the status page is rendered
+        THEN it shows the login link, the last scrape and the escaped zone
+        */
+
+        // GIVEN
+        let status = Status {
+            authenticated: false,
+            pending_verification_uri: Some(
+                "https://login.tado.com/oauth2/device?user_code=ABCD".to_string(),
+            ),
+            last_scrape: Some(UNIX_EPOCH + Duration::from_secs(1662227021)),
+            last_successful_scrape: None,
+            zones: vec![StatusZone {
+                home: "Home".to_string(),
+                name: "<Office>".to_string(),
+                zone_type: "heating",
+            }],
+        };
+
+        // WHEN
+        let actual = render(&status);
+
+        // THEN
+        assert!(actual.contains("<a href=\"https://login.tado.com/oauth2/device?user_code=ABCD\">"));
+        assert!(actual.contains("Last scrape: 2022-09-03T17:43:41+00:00"));
+        assert!(actual.contains("Last successful scrape: never"));
+        assert!(actual.contains("<td>&lt;Office&gt;</td><td>heating</td>"));
+    }
+}
@@ -0,0 +1,267 @@
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+/// Token files are only readable by the owner.
+const MODE: u32 = 0o600;
+
+/// Path of the previous token, kept as a backup.
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".tmp")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Replace the token file content atomically.
+///
+/// The content is written to a temporary file which is renamed over the token file,
+/// so a crash never leaves a truncated token behind. The previous token is kept as
+/// a backup first.
+///
+/// When the token file can't be replaced, e.g. because it is bind mounted on its own
+/// or its directory is not writable, it is rewritten in place instead.
+///
+/// Nothing is written when the file already holds the content, so the backup is kept.
+pub fn write(path: &Path, content: &str) -> io::Result<()> {
+    if fs::read_to_string(path).is_ok_and(|current| current == content) {
+        return Ok(());
+    }
+
+    let temporary = temporary_path(path);
+
+    if let Err(e) = write_new(&temporary, content) {
+        if e.kind() == ErrorKind::PermissionDenied && path.exists() {
+            warn!(
+                "unable to create {}, rewriting the token file in place: {e}",
+                temporary.display()
+            );
+            return write_in_place(path, content);
+        }
+        return Err(e);
+    }
+
+    if path.exists() {
+        let backup = backup_path(path);
+        if let Err(e) = fs::copy(path, &backup)
+            .and_then(|_| fs::set_permissions(&backup, Permissions::from_mode(MODE)))
+        {
+            warn!("unable to back up token file {}: {e}", path.display());
+        }
+    }
+
+    match fs::rename(&temporary, path) {
+        // The rename is only durable once the directory is synced.
+        Ok(()) => sync_parent(path),
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::ResourceBusy | ErrorKind::CrossesDevices
+            ) =>
+        {
+            let _ = fs::remove_file(&temporary);
+            warn!(
+                "unable to replace token file {}, rewriting it in place: {e}",
+                path.display()
+            );
+            write_in_place(path, content)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temporary);
+            Err(e)
+        }
+    }
+}
+
+/// Write a file only readable by the owner.
+fn write_new(path: &Path, content: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(MODE)
+        .open(path)?;
+    // The mode is only applied on creation, a leftover temporary file may be more permissive.
+    file.set_permissions(Permissions::from_mode(MODE))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()
+}
+
+/// Overwrite the existing token file, keeping its inode and permissions.
+///
+/// Unlike the rename this is not atomic, but works for files mounted on their own.
+fn write_in_place(path: &Path, content: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()
+}
+
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)?.sync_all()
+}
+
+/// Read the token file, falling back to the backup when it is missing or empty.
+pub fn read(path: &Path) -> Option<String> {
+    if let Some(content) = read_file(path) {
+        return Some(content);
+    }
+
+    let backup = read_file(&backup_path(path));
+    if backup.is_some() {
+        warn!(
+            "token file {} is unusable, using its backup",
+            path.display()
+        );
+    }
+
+    backup
+}
+
+/// Read the backup of the token file.
+pub fn read_backup(path: &Path) -> Option<String> {
+    read_file(&backup_path(path))
+}
+
+fn read_file(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let content = content.trim();
+
+    if content.is_empty() {
+        None
+    } else {
+        Some(content.to_string())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::os::unix::fs::MetadataExt;
+
+    /// Path of a token file in a temporary directory, without a token or a backup.
+    pub(crate) fn token_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tado-exporter-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(backup_path(&path));
+
+        path
+    }
+
+    #[test]
+    fn test_write() {
+        /*
+        GIVEN an existing token file
+        WHEN a new token is written
+        THEN the file is replaced with owner only permissions and the previous token is kept
+        */
+
+        // GIVEN
+        let path = token_path("test_write");
+        write(&path, "first").unwrap();
+
+        // WHEN
+        write(&path, "second").unwrap();
+
+        // THEN
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(read_backup(&path), Some("first".to_string()));
+        assert!(!temporary_path(&path).exists());
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        let mode = fs::metadata(backup_path(&path))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    fn test_write_unchanged() {
+        /*
+        GIVEN a token file and its backup
+        WHEN the same token is written again
+        THEN the backup still holds the previous token
+        */
+
+        // GIVEN
+        let path = token_path("test_write_unchanged");
+        write(&path, "first").unwrap();
+        write(&path, "second").unwrap();
+
+        // WHEN
+        write(&path, "second").unwrap();
+
+        // THEN
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(read_backup(&path), Some("first".to_string()));
+    }
+
+    #[test]
+    fn test_write_in_place() {
+        /*
+        GIVEN an existing token file
+        WHEN it is rewritten in place
+        THEN the same file holds the new token
+        */
+
+        // GIVEN
+        let path = token_path("test_write_in_place");
+        fs::write(&path, "a longer first token").unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+
+        // WHEN
+        write_in_place(&path, "second").unwrap();
+
+        // THEN
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+    }
+
+    #[test]
+    fn test_read_falls_back_to_backup() {
+        /*
+        GIVEN an empty token file and a backup
+        WHEN the token is read
+        THEN the backup token is returned
+        */
+
+        // GIVEN
+        let path = token_path("test_read_falls_back_to_backup");
+        fs::write(&path, "").unwrap();
+        fs::write(backup_path(&path), "backup\n").unwrap();
+
+        // WHEN
+        let actual = read(&path);
+
+        // THEN
+        assert_eq!(actual, Some("backup".to_string()));
+    }
+
+    #[test]
+    fn test_read_missing() {
+        let path = token_path("test_read_missing");
+
+        assert_eq!(read(&path), None);
+    }
+}
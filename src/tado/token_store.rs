@@ -0,0 +1,278 @@
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde_derive::{Deserialize, Serialize};
+
+use super::token_file;
+
+/// Tokens persisted between restarts, stored as a JSON document.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredTokens {
+    pub refresh_token: String,
+    #[serde(default)]
+    pub access_token: Option<String>,
+    /// Unix timestamp the access token expires at.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Client id the tokens were issued to.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Identifiers of the scraped homes.
+    #[serde(default)]
+    pub home_ids: Vec<i32>,
+}
+
+impl StoredTokens {
+    /// Parse the stored content, which is either a JSON document or, for files written
+    /// by older versions, the plain refresh token.
+    pub fn parse(content: &str) -> Result<StoredTokens, serde_json::Error> {
+        if content.trim_start().starts_with('{') {
+            return serde_json::from_str(content);
+        }
+
+        Ok(StoredTokens {
+            refresh_token: content.to_string(),
+            access_token: None,
+            expires_at: None,
+            client_id: None,
+            home_ids: Vec::new(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Persistence of the tado° tokens.
+pub trait TokenStore: Send + Sync {
+    /// Load the stored token, `None` when no token was stored yet.
+    fn load(&self) -> io::Result<Option<String>>;
+
+    /// Load the token stored before the current one, if the store keeps it.
+    fn load_backup(&self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn save(&mut self, token: &str) -> io::Result<()>;
+}
+
+/// Store the token in a plain text file.
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> FileTokenStore {
+        FileTokenStore { path: path.into() }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> io::Result<Option<String>> {
+        Ok(token_file::read(&self.path))
+    }
+
+    fn load_backup(&self) -> io::Result<Option<String>> {
+        Ok(token_file::read_backup(&self.path))
+    }
+
+    fn save(&mut self, token: &str) -> io::Result<()> {
+        token_file::write(&self.path, token)
+    }
+}
+
+/// Keep the token in memory only, a restart requires a new device authentication.
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    token: Option<String>,
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self) -> io::Result<Option<String>> {
+        Ok(self.token.clone())
+    }
+
+    fn save(&mut self, token: &str) -> io::Result<()> {
+        self.token = Some(token.to_string());
+
+        Ok(())
+    }
+}
+
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// Store the token in a file encrypted with AES-256-GCM.
+///
+/// The file contains the base64 encoded nonce, ciphertext and authentication tag.
+pub struct EncryptedFileTokenStore {
+    path: PathBuf,
+    key: [u8; 32],
+}
+
+impl EncryptedFileTokenStore {
+    pub fn new(path: impl Into<PathBuf>, key: [u8; 32]) -> EncryptedFileTokenStore {
+        EncryptedFileTokenStore {
+            path: path.into(),
+            key,
+        }
+    }
+
+    fn encrypt(&self, token: &str) -> io::Result<String> {
+        let mut nonce = [0; NONCE_LENGTH];
+        rand_bytes(&mut nonce)?;
+
+        let mut tag = [0; TAG_LENGTH];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            &[],
+            token.as_bytes(),
+            &mut tag,
+        )?;
+
+        Ok(BASE64.encode([&nonce[..], &ciphertext, &tag].concat()))
+    }
+
+    fn decrypt(&self, content: &str) -> io::Result<String> {
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+
+        let data = BASE64
+            .decode(content)
+            .map_err(|_| invalid("encrypted token file is not base64 encoded"))?;
+        if data.len() < NONCE_LENGTH + TAG_LENGTH {
+            return Err(invalid("encrypted token file is too short"));
+        }
+
+        let (nonce, rest) = data.split_at(NONCE_LENGTH);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+        let token = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(nonce),
+            &[],
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| invalid("unable to decrypt token file, is the key correct?"))?;
+
+        String::from_utf8(token).map_err(|_| invalid("decrypted token is not valid UTF-8"))
+    }
+
+    fn read(&self, content: Option<String>) -> io::Result<Option<String>> {
+        content.map(|c| self.decrypt(&c)).transpose()
+    }
+}
+
+impl TokenStore for EncryptedFileTokenStore {
+    fn load(&self) -> io::Result<Option<String>> {
+        self.read(token_file::read(&self.path))
+    }
+
+    fn load_backup(&self) -> io::Result<Option<String>> {
+        self.read(token_file::read_backup(&self.path))
+    }
+
+    fn save(&mut self, token: &str) -> io::Result<()> {
+        let content = self.encrypt(token)?;
+
+        token_file::write(&self.path, &content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    use crate::tado::token_file::tests::token_path;
+
+    fn read_raw(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_stored_tokens_parse() {
+        let tokens = StoredTokens {
+            refresh_token: "refresh".to_string(),
+            access_token: Some("access".to_string()),
+            expires_at: Some(1662227021),
+            client_id: Some("client".to_string()),
+            home_ids: vec![123],
+        };
+
+        assert_eq!(StoredTokens::parse(&tokens.to_json()).unwrap(), tokens);
+    }
+
+    #[test]
+    fn test_stored_tokens_parse_plain_text() {
+        let actual = StoredTokens::parse("refresh").unwrap();
+
+        assert_eq!(actual.refresh_token, "refresh");
+        assert_eq!(actual.access_token, None);
+        assert_eq!(actual.client_id, None);
+    }
+
+    #[test]
+    fn test_stored_tokens_parse_invalid() {
+        let actual = StoredTokens::parse("{\"refresh_token\": \"refresh\"");
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn test_memory_token_store() {
+        let mut store = MemoryTokenStore::default();
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save("token").unwrap();
+
+        assert_eq!(store.load().unwrap(), Some("token".to_string()));
+        assert_eq!(store.load_backup().unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_token_store() {
+        let path = token_path("test_file_token_store");
+        let mut store = FileTokenStore::new(&path);
+
+        store.save("first").unwrap();
+        store.save("second").unwrap();
+
+        assert_eq!(read_raw(&path), "second");
+        assert_eq!(store.load().unwrap(), Some("second".to_string()));
+        assert_eq!(store.load_backup().unwrap(), Some("first".to_string()));
+    }
+
+    #[test]
+    fn test_encrypted_file_token_store() {
+        /*
+        GIVEN an encrypted file token store
+        WHEN tokens are saved
+        THEN they are not stored in clear text and can only be loaded with the same key
+        */
+
+        // GIVEN
+        let path = token_path("test_encrypted_file_token_store");
+        let mut store = EncryptedFileTokenStore::new(&path, [7; 32]);
+
+        // WHEN
+        store.save("first").unwrap();
+        store.save("second").unwrap();
+
+        // THEN
+        assert!(!read_raw(&path).contains("second"));
+        assert_eq!(store.load().unwrap(), Some("second".to_string()));
+        assert_eq!(store.load_backup().unwrap(), Some("first".to_string()));
+
+        let other_key = EncryptedFileTokenStore::new(&path, [8; 32]);
+        assert_eq!(other_key.load().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}